@@ -0,0 +1,62 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::Path;
+
+use crate::cli;
+use crate::forecast;
+use crate::history;
+use crate::quota;
+
+/// Runs the forecast checks and the quota rules on a snapshot,
+/// returning whether all of them passed
+pub async fn run(args: &cli::CheckArgs, history_dir: &Path, default_root: &Path) -> Result<bool> {
+    let options = args.forecast.options();
+    let mut passed = true;
+
+    for dir in history::select_namespaces(history_dir, &args.namespace, default_root).await? {
+        for forecast in forecast::namespace(&dir, &options).await? {
+            let full = match forecast.days {
+                Some(days) => days <= f64::from(args.fill_within),
+                None => false,
+            };
+            if full {
+                passed = false;
+                println!("FAIL  {}", forecast);
+            } else {
+                println!("OK    {}", forecast);
+            }
+        }
+    }
+
+    if !args.quota.is_empty() {
+        let path =
+            history::resolve(history_dir, &args.namespace, default_root, &args.snapshot).await?;
+        let report = history::read(&path).await?;
+        println!("Quotas of {}:", path.display());
+        let outcomes = quota::evaluate(
+            &args.quota,
+            &report.meta.root,
+            &report.tree,
+            report.meta.shake,
+        )?;
+        passed &= quota::report(&outcomes);
+    }
+
+    Ok(passed)
+}
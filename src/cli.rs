@@ -16,12 +16,22 @@
 #[allow(unused_imports)]
 use crate::result::{make_err, Result};
 
-use std::path::PathBuf;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::result::Result as ResultOf;
 use std::str::FromStr;
 
+#[cfg(feature = "history")]
+use structopt::clap::ArgMatches;
 use structopt::StructOpt;
 
+use crate::{config, quota, tree};
+
+#[cfg(feature = "history")]
+use crate::{export, forecast, history, import, summary};
+
 #[derive(Debug)]
 pub struct Byte(pub u64);
 
@@ -35,46 +45,124 @@ impl FromStr for Byte {
     }
 }
 
+impl fmt::Display for Byte {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let byte = byte_unit::Byte::from_bytes(u128::from(self.0));
+        fmt::Display::fmt(&byte.get_appropriate_unit(true), f)
+    }
+}
+
+/// The scanned directory that history commands operate on if `--root` is not passed
+#[cfg(feature = "history")]
+pub const DEFAULT_ROOT: &str = "/";
+
+/// The directory to scan if none is passed or configured
+pub const DEFAULT_DIR: &str = "/";
+/// The leaf shaking threshold if none is passed or configured
+pub const DEFAULT_SHAKE: &str = "1MiB";
+/// The history file format if none is passed or configured
+pub const DEFAULT_HISTORY_FORMAT: &str = "json.gz";
+/// The web frontend host if none is passed or configured
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+/// The web frontend port if none is passed or configured
+pub const DEFAULT_PORT: u16 = 8000;
+
+/// The global options, each taking a value, which are skipped to find the subcommand
+const GLOBAL_OPTIONS: &[&str] = &["--history-dir", "--profile"];
+
+/// The names of subcommands, used to tell them apart from the directory in `fstree <dir>`
+const SUBCOMMANDS: &[&str] = &[
+    "scan", "serve", "tree", "browse", "history", "diff", "trend", "check", "export", "import",
+    "render", "config", "help",
+];
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "fstree")]
 pub struct CommandArgs {
-    /// The directory to search
-    #[structopt(default_value = "/", parse(from_os_str))]
-    pub dir: PathBuf,
+    /// The directory to store history files in, defaults to ~/.fstree/history
+    #[cfg(feature = "history")]
+    #[structopt(long, global = true, parse(from_os_str))]
+    pub history_dir: Option<PathBuf>,
+
+    /// Apply the values under `[profiles.<name>]` in the config files
+    #[structopt(long, global = true)]
+    pub profile: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+
+    /// The config that options not passed on the command line were filled in from
+    #[structopt(skip)]
+    pub config: config::Config,
+}
+
+/// The history directory used when none is configured
+pub fn default_history_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("Failed to get home directory")
+        .join(".fstree/history")
+}
+
+#[cfg(feature = "history")]
+impl CommandArgs {
+    pub fn history_dir(&self) -> PathBuf {
+        match &self.history_dir {
+            Some(dir) => dir.clone(),
+            None => default_history_dir(),
+        }
+    }
+}
 
-    /// The upper bound for leaf shaking. Files below this size will be blackboxed in storage and visualization (but still contribute to total size)
-    #[structopt(long, default_value = "1MiB", parse(try_from_str))]
-    pub shake: Byte,
+#[derive(Debug, StructOpt)]
+pub struct ScanArgs {
+    /// The directory to search, defaults to `/`
+    #[structopt(parse(try_from_os_str = parse_dir))]
+    pub dir: Option<PathBuf>,
+
+    /// The upper bound for leaf shaking, defaults to 1MiB. Files below this size will be blackboxed in storage and visualization (but still contribute to total size)
+    #[structopt(long, parse(try_from_str))]
+    pub shake: Option<Byte>,
 
-    /// Exit directly rather than starting web frontend to view the report
+    /// Skip this path when scanning, in addition to those in the config file.
+    /// Can be passed multiple times
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    pub exclude: Vec<PathBuf>,
+
+    /// Print the report as a tree in the terminal rather than starting web frontend to view it
     #[cfg(feature = "web")]
     #[structopt(long)]
     pub no_web: bool,
 
-    /// Run web server only and skip scanning
-    #[cfg(feature = "web")]
+    /// Browse the report in an interactive terminal UI
+    /// rather than starting web frontend or printing it as a tree
+    #[cfg(feature = "tui")]
     #[structopt(long)]
-    pub web_only: bool,
+    pub tui: bool,
 
-    /// The hostmask to start web frontend on
-    #[cfg(feature = "web")]
-    #[structopt(long, default_value = "127.0.0.1")]
-    pub host: String,
+    #[structopt(flatten)]
+    pub quota: QuotaArgs,
+
+    /// Print nothing after scanning, rather than the report as a tree in the terminal
+    #[structopt(long)]
+    pub no_tree: bool,
+
+    #[structopt(flatten)]
+    pub tree: TreeViewArgs,
 
-    /// The port to start web frontend on
     #[cfg(feature = "web")]
-    #[structopt(short, long, default_value = "8000")]
-    pub port: u16,
+    #[structopt(flatten)]
+    pub web: WebArgs,
 
     /// Skips writing history file
     #[cfg(feature = "history")]
     #[structopt(long)]
     pub no_write: bool,
 
-    /// The directory to store history files in
+    /// The format to write history files in, one of `json.gz` (the default), `json.zst`,
+    /// `bin.gz` and `bin.zst`. Files in any format can be read regardless of this option
     #[cfg(feature = "history")]
-    #[structopt(long, parse(from_os_str))]
-    pub history_dir: Option<PathBuf>,
+    #[structopt(long, parse(try_from_str))]
+    pub history_format: Option<history::Format>,
 
     /// Prevent performing log rotation after writing history file;
     /// this option is ignored if --no-write is passed
@@ -82,10 +170,683 @@ pub struct CommandArgs {
     #[structopt(long)]
     pub no_rotate: bool,
 
-    /// Files older than this number of days will be removed during log rotation
     #[cfg(feature = "history")]
+    #[structopt(flatten)]
+    pub retention: RetentionArgs,
+
+    /// Write metrics of the scan in the Prometheus exposition format to this file,
+    /// such as in the directory of the node_exporter textfile collector
+    #[cfg(feature = "history")]
+    #[structopt(long, parse(from_os_str))]
+    pub prometheus: Option<PathBuf>,
+
+    #[cfg(feature = "history")]
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
+
+    /// Write the report as JSON to this file, or `-` for standard output,
+    /// instead of writing history or viewing it
+    #[cfg(feature = "history")]
+    #[structopt(long, parse(from_os_str), conflicts_with = "summary")]
+    pub json: Option<PathBuf>,
+
+    /// The shape of the JSON written with --json: `report` for the whole report,
+    /// `summary` for the total stats without the tree,
+    /// or `lines` for one JSON object per line for each node
+    #[cfg(feature = "history")]
+    #[structopt(long, default_value = "report", parse(try_from_str))]
+    pub json_mode: export::JsonMode,
+
+    /// Print a summary of the scan to standard output, either `markdown` or `text`,
+    /// instead of the tree
+    #[cfg(feature = "history")]
+    #[structopt(long, parse(try_from_str))]
+    pub summary: Option<summary::Style>,
+
+    /// The number of entries in each section of the summary
+    #[cfg(feature = "history")]
+    #[structopt(long, default_value = "10")]
+    pub summary_top: usize,
+}
+
+impl ScanArgs {
+    pub fn dir(&self) -> &Path {
+        self.dir
+            .as_ref()
+            .map_or(Path::new(DEFAULT_DIR), PathBuf::as_path)
+    }
+
+    pub fn shake(&self) -> u64 {
+        match &self.shake {
+            Some(shake) => shake.0,
+            None => DEFAULT_SHAKE.parse::<Byte>().expect("Invalid default").0,
+        }
+    }
+
+    #[cfg(feature = "history")]
+    pub fn history_format(&self) -> history::Format {
+        self.history_format
+            .unwrap_or_else(|| DEFAULT_HISTORY_FORMAT.parse().expect("Invalid default"))
+    }
+
+    /// Whether to skip printing the tree, which is implied by `--summary`
+    pub fn no_tree(&self) -> bool {
+        #[cfg(feature = "history")]
+        {
+            if self.summary.is_some() {
+                return true;
+            }
+        }
+        self.no_tree
+    }
+}
+
+fn parse_dir(str: &OsStr) -> ResultOf<PathBuf, OsString> {
+    let path = PathBuf::from(str);
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(format!("{}: not a directory", path.display()).into())
+    }
+}
+
+#[cfg(feature = "web")]
+#[derive(Debug, StructOpt)]
+pub struct WebArgs {
+    /// The hostmask to start web frontend on, defaults to 127.0.0.1
+    #[structopt(long)]
+    pub host: Option<String>,
+
+    /// The port to start web frontend on, defaults to 8000
+    #[structopt(short, long)]
+    pub port: Option<u16>,
+}
+
+#[cfg(feature = "web")]
+impl WebArgs {
+    pub fn host(&self) -> &str {
+        self.host.as_ref().map_or(DEFAULT_HOST, String::as_str)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+}
+
+/// Quota rules, which fail the command if violated
+#[derive(Debug, StructOpt)]
+pub struct QuotaArgs {
+    /// Fail if the real size of a path exceeds a limit, such as `/var/log=20G`.
+    /// Relative paths are relative to the scanned directory. Can be passed multiple times
+    #[structopt(long, number_of_values = 1, parse(try_from_str))]
+    pub max: Vec<quota::PathRule>,
+
+    /// Fail if the total real size of files with an extension exceeds a limit, such as `log=5G`.
+    /// Can be passed multiple times
+    #[structopt(long, number_of_values = 1, parse(try_from_str))]
+    pub max_ext: Vec<quota::ExtensionRule>,
+
+    /// Fail if the scan encountered more than this number of errors
+    #[structopt(long)]
+    pub max_errors: Option<usize>,
+}
+
+impl QuotaArgs {
+    pub fn is_empty(&self) -> bool {
+        self.max.is_empty() && self.max_ext.is_empty() && self.max_errors.is_none()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TreeViewArgs {
+    /// The number of directory levels to print in the terminal tree view
+    #[structopt(long, default_value = "3")]
+    pub depth: usize,
+
+    /// The number of largest entries to print for each directory in the terminal tree view
+    #[structopt(long, default_value = "10")]
+    pub entries: usize,
+}
+
+impl TreeViewArgs {
+    pub fn options(&self) -> tree::Options {
+        tree::Options {
+            depth: self.depth,
+            entries: self.entries,
+            width: tree::screen_width(),
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct TreeArgs {
+    #[structopt(flatten)]
+    pub snapshot: SnapshotArgs,
+
+    /// Print the subtree at this path relative to the scanned directory
+    #[structopt(long, parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub view: TreeViewArgs,
+}
+
+#[cfg(all(feature = "tui", feature = "history"))]
+#[derive(Debug, StructOpt)]
+pub struct BrowseArgs {
+    #[structopt(flatten)]
+    pub snapshot: SnapshotArgs,
+}
+
+#[cfg(feature = "web")]
+#[derive(Debug, StructOpt)]
+pub struct ServeArgs {
+    /// A history snapshot to view, either the path to a history file,
+    /// `latest`, `latest~N` or a unique prefix of a history file name
+    #[cfg(feature = "history")]
+    pub snapshot: Option<String>,
+
+    /// The scanned directory to view the history of, defaults to `/`
+    #[cfg(feature = "history")]
+    #[structopt(long, parse(from_os_str))]
+    pub root: Option<PathBuf>,
+
+    /// The host that scanned the directory, defaults to this machine.
+    /// Named apart from `--host`, which sets the hostmask of the web frontend
+    #[cfg(feature = "history")]
+    #[structopt(long)]
+    pub snapshot_host: Option<String>,
+
+    /// Look up the snapshot in the history of all scanned directories and hosts
+    #[cfg(feature = "history")]
+    #[structopt(long)]
+    pub all_namespaces: bool,
+
+    #[structopt(flatten)]
+    pub web: WebArgs,
+}
+
+#[cfg(all(feature = "web", feature = "history"))]
+impl ServeArgs {
+    /// The namespaces to look up the snapshot in
+    pub fn namespace(&self) -> NamespaceArgs {
+        NamespaceArgs {
+            root: self.root.clone(),
+            host: self.snapshot_host.clone(),
+            all_namespaces: self.all_namespaces,
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct RetentionArgs {
+    /// Files older than this number of days will be removed during log rotation.
+    /// Defaults to 30 if no other retention policy is specified
+    #[structopt(long)]
+    pub rotate_days: Option<u32>,
+
+    /// Only keep this number of most recent history files during log rotation
+    #[structopt(long)]
+    pub keep_last: Option<usize>,
+
+    /// Keep one history file per day for a week, per week for a month and per month for a year
+    /// during log rotation
+    #[structopt(long)]
+    pub keep_gfs: bool,
+
+    /// Don't keep history files by the --keep-gfs schedule, even if the config file enables it
+    #[structopt(long, conflicts_with = "keep-gfs")]
+    pub no_keep_gfs: bool,
+
+    /// Remove the oldest history files during log rotation
+    /// until the history directory is no larger than this size
+    #[structopt(long, parse(try_from_str))]
+    pub max_history_size: Option<Byte>,
+}
+
+#[cfg(feature = "history")]
+impl RetentionArgs {
+    pub fn policy(&self) -> history::Policy {
+        let mut policy = history::Policy {
+            max_age_days: self.rotate_days,
+            keep_last: self.keep_last,
+            gfs: self.keep_gfs,
+            max_total_size: self.max_history_size.as_ref().map(|size| size.0),
+        };
+        if policy.max_age_days.is_none()
+            && policy.keep_last.is_none()
+            && !policy.gfs
+            && policy.max_total_size.is_none()
+        {
+            policy.max_age_days = Some(30);
+        }
+        policy
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Scan a directory, store the report in history and view it in a browser or the terminal
+    Scan(ScanArgs),
+    /// Start the web frontend without scanning
+    #[cfg(feature = "web")]
+    Serve(ServeArgs),
+    /// Print a history snapshot as a tree in the terminal
+    #[cfg(feature = "history")]
+    Tree(TreeArgs),
+    /// Browse a history snapshot in an interactive terminal UI
+    #[cfg(all(feature = "tui", feature = "history"))]
+    Browse(BrowseArgs),
+    /// Inspect and maintain the history directory
+    #[cfg(feature = "history")]
+    History(HistoryCommand),
+    /// Compare two history snapshots
+    #[cfg(feature = "history")]
+    Diff(DiffArgs),
+    /// Show the size of a path over all history snapshots
+    #[cfg(feature = "history")]
+    Trend(TrendArgs),
+    /// Check the forecasts from history, exiting with a non-zero code on failure
+    #[cfg(feature = "history")]
+    Check(CheckArgs),
+    /// Export a history snapshot for use in other tools
+    #[cfg(feature = "history")]
+    Export(ExportArgs),
+    /// Import a report from another tool into history,
+    /// after which it can be viewed with `fstree serve --root <root> latest`,
+    /// adding `--snapshot-host <host>` if it was imported with `--host`
+    #[cfg(feature = "history")]
+    Import(ImportArgs),
+    /// Render a history snapshot as an image
+    #[cfg(feature = "history")]
+    Render(RenderCommand),
+    /// Inspect the config files
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ConfigCommand {
+    /// Print the merged config with the source of each value
+    Show,
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub enum HistoryCommand {
+    /// List the snapshots in the history directory
+    List {
+        #[structopt(flatten)]
+        namespace: NamespaceArgs,
+    },
+    /// Show the details of a snapshot
+    Show {
+        #[structopt(flatten)]
+        snapshot: SnapshotArgs,
+    },
+    /// Remove snapshots from the history directory
+    Rm {
+        /// The snapshots to remove, in the same forms as in `show`
+        #[structopt(required = true)]
+        snapshots: Vec<String>,
+
+        #[structopt(flatten)]
+        namespace: NamespaceArgs,
+
+        /// Print the files that would be removed without removing them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Remove old history files according to the retention policy
+    Rotate {
+        #[structopt(flatten)]
+        namespace: NamespaceArgs,
+
+        #[structopt(flatten)]
+        retention: RetentionArgs,
+
+        /// Print the files that would be removed without removing them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Rewrite history files written in older schema versions into the current one.
+    ///
+    /// Files written before the scanned directory was recorded are only migrated
+    /// if `--root` names the directory they were scanned from.
+    Migrate {
+        #[structopt(flatten)]
+        namespace: NamespaceArgs,
+
+        /// Print the files that would be migrated without rewriting them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Rebuild the history index from the snapshot files
+    Reindex {
+        #[structopt(flatten)]
+        namespace: NamespaceArgs,
+    },
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct DiffArgs {
+    /// The earlier snapshot, either the path to a history file,
+    /// `latest`, `latest~N` or a unique prefix of a history file name
+    pub before: String,
+
+    /// The later snapshot, in the same forms as `before`
+    pub after: String,
+
+    #[structopt(flatten)]
+    pub namespace: NamespaceArgs,
+
+    /// The maximum number of entries to print in each section
     #[structopt(long, default_value = "30")]
-    pub rotate_days: u32,
+    pub limit: usize,
+
+    /// Hide changes smaller than this size
+    #[structopt(long, default_value = "0", parse(try_from_str))]
+    pub min_delta: Byte,
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct TrendArgs {
+    /// The path to show the size history of
+    #[structopt(parse(from_os_str))]
+    pub path: PathBuf,
+
+    #[structopt(flatten)]
+    pub namespace: NamespaceArgs,
+
+    /// The number of largest children to show growth rates for
+    #[structopt(long, default_value = "5")]
+    pub children: usize,
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct CheckArgs {
+    #[structopt(flatten)]
+    pub namespace: NamespaceArgs,
+
+    #[structopt(flatten)]
+    pub forecast: ForecastArgs,
+
+    /// Fail if a filesystem is forecast to fill up within this number of days
+    #[structopt(long, default_value = "7")]
+    pub fill_within: u32,
+
+    #[structopt(flatten)]
+    pub quota: QuotaArgs,
+
+    /// The snapshot to evaluate quota rules against, either the path to a history file,
+    /// `latest`, `latest~N` or a unique prefix of a history file name
+    #[structopt(long, default_value = "latest")]
+    pub snapshot: String,
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct ForecastArgs {
+    /// The regression used to estimate growth, either `linear` or `robust`
+    #[structopt(long, default_value = "robust", parse(try_from_str))]
+    pub forecast_method: forecast::Method,
+
+    /// Only use snapshots from this number of days before the latest one for forecasting
+    #[structopt(long, default_value = "30")]
+    pub forecast_window: u32,
+
+    /// Also forecast for this number of largest directories under the scanned directory
+    #[structopt(long, default_value = "5")]
+    pub forecast_dirs: usize,
+}
+
+#[cfg(feature = "history")]
+impl ForecastArgs {
+    pub fn options(&self) -> forecast::Options {
+        forecast::Options {
+            method: self.forecast_method,
+            window_days: self.forecast_window,
+            dirs: self.forecast_dirs,
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct ExportArgs {
+    /// The format to export in: `ncdu` for the JSON dump format of ncdu,
+    /// `csv` or `tsv` for one row per node,
+    /// `folded` for the folded stack format of flame graph tools,
+    /// `flamegraph` for an SVG flame graph,
+    /// `html` for a single page viewable offline,
+    /// `json` for the whole report, `json-summary` for the total stats without the tree,
+    /// `jsonl` for one JSON object per line for each node,
+    /// `markdown` or `text` for a summary of the largest entries and changes,
+    /// `prometheus` for metrics in the Prometheus exposition format,
+    /// or `sqlite` to add the snapshot to a SQLite database if built with the `sqlite` feature
+    #[structopt(parse(try_from_str))]
+    pub format: export::Format,
+
+    #[structopt(flatten)]
+    pub snapshot: SnapshotArgs,
+
+    /// The file to write to, defaults to standard output
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Only export nodes up to this depth below the scanned directory, for csv, tsv and jsonl
+    #[structopt(long)]
+    pub max_depth: Option<usize>,
+
+    /// Only export nodes at least this large, for csv, tsv and jsonl
+    #[structopt(long, parse(try_from_str))]
+    pub min_size: Option<Byte>,
+
+    /// Also write the total stats of each extension to this file, for csv and tsv
+    #[structopt(long, parse(from_os_str))]
+    pub extension_summary: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub metrics: MetricsArgs,
+
+    /// The quantity that sizes frames, for folded and flamegraph:
+    /// `real` or `content` for the sizes or `files` for the number of files
+    #[structopt(long, default_value = "real", parse(try_from_str))]
+    pub weight: export::Weight,
+
+    /// Draw the flame graph upside down with the scanned directory at the top
+    #[structopt(long)]
+    pub icicle: bool,
+
+    /// The number of entries in each section, for markdown and text
+    #[structopt(long, default_value = "10")]
+    pub top: usize,
+}
+
+/// The export options that only apply to some formats
+#[cfg(feature = "history")]
+const EXPORT_FORMAT_OPTIONS: &[&str] = &[
+    "max-depth",
+    "min-size",
+    "extension-summary",
+    "metrics-depth",
+    "metrics-path",
+    "metrics-extensions",
+    "weight",
+    "icicle",
+    "top",
+];
+
+#[cfg(feature = "history")]
+impl ExportArgs {
+    /// Rejects the options passed on the command line that the format doesn't use
+    fn check_options(&self, matches: &ArgMatches) -> Result {
+        for option in EXPORT_FORMAT_OPTIONS {
+            if matches.occurrences_of(option) > 0 && !self.format.uses_option(option) {
+                return Err(make_err(format!(
+                    "--{} does not apply to the {} export format",
+                    option,
+                    matches.value_of("format").unwrap_or_default(),
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub enum RenderCommand {
+    /// Render a squarified treemap as SVG, or PNG if the output ends with `.png`
+    Treemap(TreemapArgs),
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct TreemapArgs {
+    #[structopt(flatten)]
+    pub snapshot: SnapshotArgs,
+
+    /// The file to write the image to
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Render the subtree at this path relative to the scanned directory
+    #[structopt(long, parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    /// The width of the image in pixels
+    #[structopt(long, default_value = "1600")]
+    pub width: u32,
+
+    /// The height of the image in pixels
+    #[structopt(long, default_value = "1000")]
+    pub height: u32,
+
+    /// The number of directory levels to draw
+    #[structopt(long, default_value = "3")]
+    pub depth: usize,
+
+    /// The quantity that sizes rectangles:
+    /// `real` or `content` for the sizes or `files` for the number of files
+    #[structopt(long, default_value = "real", parse(try_from_str))]
+    pub weight: export::Weight,
+}
+
+/// Selects the metrics written in the Prometheus exposition format
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct MetricsArgs {
+    /// Write the sizes of directories up to this depth below the scanned directory as metrics
+    #[structopt(long, default_value = "1")]
+    pub metrics_depth: usize,
+
+    /// Write the sizes of only these directories, relative to the scanned directory, as metrics.
+    /// Can be passed multiple times
+    #[structopt(long, number_of_values = 1, parse(from_os_str))]
+    pub metrics_path: Vec<PathBuf>,
+
+    /// Write the totals of this number of largest extensions as metrics
+    #[structopt(long, default_value = "20")]
+    pub metrics_extensions: usize,
+}
+
+#[cfg(feature = "history")]
+impl MetricsArgs {
+    pub fn options(&self) -> export::prometheus::Options {
+        export::prometheus::Options {
+            depth: self.metrics_depth,
+            paths: self.metrics_path.clone(),
+            extensions: self.metrics_extensions,
+        }
+    }
+}
+
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct ImportArgs {
+    /// The format of the input: `ncdu` for dumps written by `ncdu -o`,
+    /// `du` for the output of `du -ab`,
+    /// or `find` for the output of `find -printf '%s %k %p\n'`
+    #[structopt(parse(try_from_str))]
+    pub format: import::Format,
+
+    /// The file to import, or `-` for standard input
+    #[structopt(parse(from_os_str))]
+    pub input: PathBuf,
+
+    /// The scanned directory to store the report under, if not recorded in the input
+    #[structopt(long, parse(from_os_str))]
+    pub root: Option<PathBuf>,
+
+    /// The host to store the report under, defaults to this machine
+    #[structopt(long)]
+    pub host: Option<String>,
+
+    /// The upper bound for leaf shaking in the imported report, defaults to 1MiB
+    #[structopt(long, parse(try_from_str))]
+    pub shake: Option<Byte>,
+
+    /// The format to store the imported report in, as in `fstree scan --history-format`
+    #[structopt(long, parse(try_from_str))]
+    pub history_format: Option<history::Format>,
+
+    /// Only check that the input can be imported, without storing it in history.
+    /// Imported reports can be viewed with `fstree serve` after storing them
+    #[structopt(long)]
+    pub no_write: bool,
+}
+
+#[cfg(feature = "history")]
+impl ImportArgs {
+    pub fn shake(&self) -> u64 {
+        match &self.shake {
+            Some(shake) => shake.0,
+            None => DEFAULT_SHAKE.parse::<Byte>().expect("Invalid default").0,
+        }
+    }
+
+    pub fn history_format(&self) -> history::Format {
+        self.history_format
+            .unwrap_or_else(|| DEFAULT_HISTORY_FORMAT.parse().expect("Invalid default"))
+    }
+}
+
+// Selects a history snapshot.
+// Not a doc comment, which would replace the help of the subcommands it is flattened into.
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct SnapshotArgs {
+    /// The snapshot, either the path to a history file,
+    /// `latest`, `latest~N` or a unique prefix of a history file name
+    #[structopt(default_value = "latest")]
+    pub snapshot: String,
+
+    #[structopt(flatten)]
+    pub namespace: NamespaceArgs,
+}
+
+// Selects the history of which scanned directories to operate on.
+// History is stored separately for each scanned directory and host.
+// Not a doc comment, which would replace the help of the subcommands it is flattened into.
+#[cfg(feature = "history")]
+#[derive(Debug, StructOpt)]
+pub struct NamespaceArgs {
+    /// The scanned directory to operate on the history of, defaults to `/`
+    #[structopt(long, parse(from_os_str))]
+    pub root: Option<PathBuf>,
+
+    /// The host that scanned the directory, defaults to this machine
+    #[structopt(long)]
+    pub host: Option<String>,
+
+    /// Operate on the history of all scanned directories and hosts
+    #[structopt(long)]
+    pub all_namespaces: bool,
 }
 
 pub fn read() -> Result<CommandArgs> {
@@ -93,6 +854,59 @@ pub fn read() -> Result<CommandArgs> {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"));
-    let args = app.get_matches();
-    Ok(CommandArgs::from_clap(&args))
+    let matches = app.get_matches_from(shorthand(env::args_os().collect()));
+    let mut args = CommandArgs::from_clap(&matches);
+
+    #[cfg(feature = "history")]
+    {
+        if let Command::Export(export_args) = &args.command {
+            if let Some(matches) = matches.subcommand_matches("export") {
+                export_args.check_options(matches)?;
+            }
+        }
+    }
+
+    let mut config = config::Config::load(args.profile.as_ref().map(String::as_str))?;
+    #[cfg(feature = "history")]
+    {
+        if let Some(dir) = &args.history_dir {
+            let dir = dir.to_string_lossy().into_owned();
+            config.set_from_cli("history_dir", toml::Value::String(dir));
+        }
+    }
+    config.apply(&mut args)?;
+    args.config = config;
+    Ok(args)
+}
+
+/// Inserts the `scan` subcommand if none is passed,
+/// so that `fstree [options] <dir>` keeps working as a shorthand of `fstree scan [options] <dir>`.
+///
+/// Global options may come before the subcommand, so they are skipped to find it.
+/// Any other option starts the implied `scan`, which also accepts the global options.
+fn shorthand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        let arg = arg.to_string_lossy();
+        if GLOBAL_OPTIONS.contains(&&*arg) {
+            i += 2;
+        } else if GLOBAL_OPTIONS
+            .iter()
+            .any(|option| arg.starts_with(&format!("{}=", option)))
+        {
+            i += 1;
+        } else if ["-h", "--help", "-V", "--version"].contains(&&*arg) {
+            return args;
+        } else {
+            break;
+        }
+    }
+
+    let is_subcommand = args
+        .get(i)
+        .map_or(false, |arg| SUBCOMMANDS.contains(&&*arg.to_string_lossy()));
+    if !is_subcommand {
+        args.insert(i.min(args.len()), OsString::from("scan"));
+    }
+    args
 }
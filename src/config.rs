@@ -0,0 +1,372 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use toml::value::{Table, Value};
+
+use crate::{cli, quota};
+
+/// The system-wide config file, overridden by the config file of the user
+const SYSTEM_CONFIG: &str = "/etc/fstree.toml";
+/// The path of the config file of the user relative to a config directory
+const USER_CONFIG: &str = "fstree/config.toml";
+
+/// The keys that can be set in config files and profiles, with their built-in defaults
+fn keys() -> Vec<(&'static str, Option<Value>)> {
+    vec![
+        ("dir", Some(Value::from(cli::DEFAULT_DIR))),
+        ("shake", Some(Value::from(cli::DEFAULT_SHAKE))),
+        ("exclude", Some(Value::Array(vec![]))),
+        (
+            "history_dir",
+            Some(Value::from(
+                cli::default_history_dir().to_string_lossy().into_owned(),
+            )),
+        ),
+        (
+            "history_format",
+            Some(Value::from(cli::DEFAULT_HISTORY_FORMAT)),
+        ),
+        ("rotate_days", None),
+        ("keep_last", None),
+        ("keep_gfs", Some(Value::from(false))),
+        ("max_history_size", None),
+        ("host", Some(Value::from(cli::DEFAULT_HOST))),
+        ("port", Some(Value::from(i64::from(cli::DEFAULT_PORT)))),
+        ("max", Some(Value::Table(Table::new()))),
+        ("max_ext", Some(Value::Table(Table::new()))),
+        ("max_errors", None),
+    ]
+}
+
+/// Values from config files, the selected profile and global command line options.
+///
+/// The top-level keys of a config file are defaults for all commands,
+/// and the tables under `[profiles.<name>]` are only applied with `--profile <name>`.
+/// Values passed on the command line override all of them.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The sources of values in increasing precedence
+    layers: Vec<Layer>,
+    profile: Option<String>,
+}
+
+#[derive(Debug)]
+struct Layer {
+    /// A description of where the values come from
+    source: String,
+    values: Table,
+}
+
+/// Replaces a leading `~` in a path from a config file with the home directory
+fn expand_home(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path,
+    }
+}
+
+/// The config files to read in increasing precedence.
+///
+/// The config file of the user is `~/.config/fstree/config.toml` on all platforms,
+/// falling back to the platform config directory if it does not exist,
+/// such as `~/Library/Application Support` on macOS.
+fn paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(SYSTEM_CONFIG)];
+    match dirs::home_dir().map(|home| home.join(".config").join(USER_CONFIG)) {
+        Some(path) if path.is_file() => paths.push(path),
+        _ => {
+            if let Some(dir) = dirs::config_dir() {
+                paths.push(dir.join(USER_CONFIG));
+            }
+        }
+    }
+    paths
+}
+
+impl Config {
+    /// Reads the config files, selecting a profile if one is passed
+    pub fn load(profile: Option<&str>) -> Result<Self> {
+        let mut files = vec![];
+        for path in paths() {
+            if !path.is_file() {
+                continue;
+            }
+            let data = fs::read_to_string(&path)?;
+            let table: Table = toml::from_str(&data)
+                .map_err(|err| make_err(format!("{}: {}", path.display(), err)))?;
+            files.push((path, table));
+        }
+
+        let mut config = Config {
+            layers: vec![],
+            profile: profile.map(str::to_string),
+        };
+        for (path, table) in &files {
+            let mut values = table.clone();
+            values.remove("profiles");
+            config.push(path.display().to_string(), values);
+        }
+
+        if let Some(profile) = profile {
+            let mut found = false;
+            for (path, table) in &files {
+                let values = table
+                    .get("profiles")
+                    .and_then(Value::as_table)
+                    .and_then(|profiles| profiles.get(profile));
+                match values {
+                    Some(Value::Table(values)) => {
+                        let source = format!("profile {} in {}", profile, path.display());
+                        config.push(source, values.clone());
+                        found = true;
+                    }
+                    Some(_) => {
+                        return Err(make_err(format!(
+                            "{}: profile {} is not a table",
+                            path.display(),
+                            profile
+                        )))
+                    }
+                    None => {}
+                }
+            }
+            if !found {
+                return Err(make_err(format!("No such profile: {}", profile)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Adds a value passed on the command line, which overrides all config files
+    pub fn set_from_cli(&mut self, key: &str, value: Value) {
+        let mut values = Table::new();
+        values.insert(key.to_string(), value);
+        self.layers.push(Layer {
+            source: "command line".to_string(),
+            values,
+        });
+    }
+
+    fn push(&mut self, source: String, values: Table) {
+        let keys = keys();
+        for key in values.keys() {
+            if !keys.iter().any(|(known, _)| known == key) {
+                log::warn!("{}: unknown config key {:?}", source, key);
+            }
+        }
+        self.layers.push(Layer { source, values });
+    }
+
+    /// Finds the value with the highest precedence for a key, with its source
+    fn find(&self, key: &str) -> Option<(&Value, &str)> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| Some((layer.values.get(key)?, &*layer.source)))
+    }
+
+    pub fn get<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: DeserializeOwned,
+    {
+        match self.find(key) {
+            Some((value, source)) => value
+                .clone()
+                .try_into()
+                .map(Some)
+                .map_err(|err| make_err(format!("{}: invalid {}: {}", source, key, err))),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets a string value and parses it like the command line option of the same name
+    pub fn get_parsed<T>(&self, key: &str) -> Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let str = match self.get::<String>(key)? {
+            Some(str) => str,
+            None => return Ok(None),
+        };
+        match str.parse() {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => {
+                let source = self.find(key).map_or("", |(_, source)| source);
+                Err(make_err(format!("{}: invalid {}: {}", source, key, err)))
+            }
+        }
+    }
+
+    /// Fills in the options not passed on the command line from the config
+    pub fn apply(&self, args: &mut cli::CommandArgs) -> Result {
+        #[cfg(feature = "history")]
+        {
+            args.history_dir = self.get::<PathBuf>("history_dir")?.map(expand_home);
+        }
+
+        match &mut args.command {
+            cli::Command::Scan(scan_args) => {
+                if scan_args.dir.is_none() {
+                    if let Some(dir) = self.get::<PathBuf>("dir")?.map(expand_home) {
+                        if !dir.is_dir() {
+                            return Err(make_err(format!("{}: not a directory", dir.display())));
+                        }
+                        scan_args.dir = Some(dir);
+                    }
+                }
+                if scan_args.shake.is_none() {
+                    scan_args.shake = self.get_parsed("shake")?;
+                }
+                if let Some(exclude) = self.get::<Vec<PathBuf>>("exclude")? {
+                    scan_args
+                        .exclude
+                        .extend(exclude.into_iter().map(expand_home));
+                }
+                #[cfg(feature = "history")]
+                {
+                    if scan_args.history_format.is_none() {
+                        scan_args.history_format = self.get_parsed("history_format")?;
+                    }
+                    self.apply_retention(&mut scan_args.retention)?;
+                }
+                self.apply_quota(&mut scan_args.quota)?;
+                #[cfg(feature = "web")]
+                self.apply_web(&mut scan_args.web)?;
+            }
+            #[cfg(feature = "history")]
+            cli::Command::Import(import_args) => {
+                if import_args.shake.is_none() {
+                    import_args.shake = self.get_parsed("shake")?;
+                }
+                if import_args.history_format.is_none() {
+                    import_args.history_format = self.get_parsed("history_format")?;
+                }
+            }
+            #[cfg(feature = "history")]
+            cli::Command::Check(check_args) => self.apply_quota(&mut check_args.quota)?,
+            #[cfg(feature = "web")]
+            cli::Command::Serve(serve_args) => self.apply_web(&mut serve_args.web)?,
+            #[cfg(feature = "history")]
+            cli::Command::History(cli::HistoryCommand::Rotate { retention, .. }) => {
+                self.apply_retention(retention)?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Adds the quota rules for the paths and extensions without rules on the command line
+    fn apply_quota(&self, quota: &mut cli::QuotaArgs) -> Result {
+        for (path, max) in self
+            .get::<BTreeMap<PathBuf, String>>("max")?
+            .unwrap_or_default()
+        {
+            let path = expand_home(path);
+            if !quota.max.iter().any(|rule| rule.path == path) {
+                let max = self.parse_size("max", &max)?;
+                quota.max.push(quota::PathRule { path, max });
+            }
+        }
+        for (extension, max) in self
+            .get::<BTreeMap<String, String>>("max_ext")?
+            .unwrap_or_default()
+        {
+            let extension = extension.trim_start_matches('.').to_string();
+            if !quota.max_ext.iter().any(|rule| rule.extension == extension) {
+                let max = self.parse_size("max_ext", &max)?;
+                quota.max_ext.push(quota::ExtensionRule { extension, max });
+            }
+        }
+        if quota.max_errors.is_none() {
+            quota.max_errors = self.get("max_errors")?;
+        }
+        Ok(())
+    }
+
+    fn parse_size(&self, key: &str, str: &str) -> Result<u64> {
+        match str.parse::<cli::Byte>() {
+            Ok(size) => Ok(size.0),
+            Err(err) => {
+                let source = self.find(key).map_or("", |(_, source)| source);
+                Err(make_err(format!("{}: invalid {}: {}", source, key, err)))
+            }
+        }
+    }
+
+    #[cfg(feature = "history")]
+    fn apply_retention(&self, retention: &mut cli::RetentionArgs) -> Result {
+        if retention.rotate_days.is_none() {
+            retention.rotate_days = self.get("rotate_days")?;
+        }
+        if retention.keep_last.is_none() {
+            retention.keep_last = self.get("keep_last")?;
+        }
+        if !retention.keep_gfs && !retention.no_keep_gfs {
+            retention.keep_gfs = self.get("keep_gfs")?.unwrap_or(false);
+        }
+        if retention.max_history_size.is_none() {
+            retention.max_history_size = self.get_parsed("max_history_size")?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "web")]
+    fn apply_web(&self, web: &mut cli::WebArgs) -> Result {
+        if web.host.is_none() {
+            web.host = self.get("host")?;
+        }
+        if web.port.is_none() {
+            web.port = self.get("port")?;
+        }
+        Ok(())
+    }
+
+    /// Prints the merged config with the source of each value
+    pub fn show(&self) {
+        match &self.profile {
+            Some(profile) => println!("# profile: {}", profile),
+            None => println!("# no profile selected"),
+        }
+        for layer in &self.layers {
+            println!("# read {}", layer.source);
+        }
+        println!();
+
+        let lines: Vec<(String, &str)> = keys()
+            .into_iter()
+            .map(|(key, default)| match (self.find(key), default) {
+                (Some((value, source)), _) => (format!("{} = {}", key, value), source),
+                (None, Some(default)) => (format!("{} = {}", key, default), "default"),
+                (None, None) => (format!("# {}", key), "not set"),
+            })
+            .collect();
+        let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+        for (line, source) in lines {
+            println!("{:<width$}  # {}", line, source, width = width);
+        }
+    }
+}
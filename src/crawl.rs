@@ -19,8 +19,9 @@ use crate::result::{make_err, Result};
 use std::borrow::Cow;
 use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::Metadata;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
@@ -90,6 +91,62 @@ impl From<io::Error> for Node {
 }
 
 impl Node {
+    pub fn name(&self) -> Option<&StringRef> {
+        match self {
+            Node::File { name, .. }
+            | Node::Dir { name, .. }
+            | Node::Link { name, .. }
+            | Node::BlockDevice { name, .. }
+            | Node::CharDevice { name, .. }
+            | Node::NamedPipe { name, .. }
+            | Node::UnixSocket { name, .. }
+            | Node::Other { name, .. } => Some(name),
+            Node::Error { name, .. } => name.as_ref(),
+        }
+    }
+
+    /// A short name of the type of this node
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Node::File { .. } => "file",
+            Node::Dir { .. } => "dir",
+            Node::Link { .. } => "link",
+            Node::Error { .. } => "error",
+            Node::BlockDevice { .. } => "block_device",
+            Node::CharDevice { .. } => "char_device",
+            Node::NamedPipe { .. } => "named_pipe",
+            Node::UnixSocket { .. } => "socket",
+            Node::Other { .. } => "other",
+        }
+    }
+
+    /// Finds a descendant by its path relative to this node
+    pub fn find(&self, path: &Path) -> Option<&Node> {
+        let mut node = self;
+        for component in path.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_str()?,
+                Component::CurDir => continue,
+                _ => return None,
+            };
+            node = match node {
+                Node::Dir { children, .. } => children
+                    .iter()
+                    .find(|child| child.name().map_or(false, |child| &**child == name))?,
+                _ => return None,
+            };
+        }
+        Some(node)
+    }
+
+    /// The aggregate stats of this node, without cloning the breakdown by extension
+    pub fn total(&self) -> TypedStats {
+        match self {
+            Node::Dir { stats, .. } => stats.total,
+            _ => self.stats().total,
+        }
+    }
+
     pub fn stats(&self) -> NodeStats {
         match self {
             Node::File {
@@ -163,6 +220,18 @@ impl From<io::Error> for StaticError {
     }
 }
 
+impl From<String> for StaticError {
+    fn from(err: String) -> Self {
+        Self(err)
+    }
+}
+
+impl fmt::Display for StaticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 #[cfg_attr(feature = "history", derive(Serialize, Deserialize))]
 pub enum FileTypeExt {
@@ -177,6 +246,24 @@ pub enum FileTypeExt {
     Other(StringRef),
 }
 
+impl fmt::Display for FileTypeExt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileTypeExt::File(ext) if ext.is_empty() => f.write_str("(no extension)"),
+            FileTypeExt::File(ext) => write!(f, ".{}", ext),
+            FileTypeExt::Dir => f.write_str("(directories)"),
+            FileTypeExt::Link => f.write_str("(symlinks)"),
+            FileTypeExt::BlockDevice => f.write_str("(block devices)"),
+            FileTypeExt::CharDevice => f.write_str("(character devices)"),
+            FileTypeExt::NamedPipe => f.write_str("(named pipes)"),
+            FileTypeExt::UnixSocket => f.write_str("(sockets)"),
+            FileTypeExt::Error => f.write_str("(errors)"),
+            FileTypeExt::Other(ext) if ext.is_empty() => f.write_str("(other)"),
+            FileTypeExt::Other(ext) => write!(f, ".{} (other)", ext),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "history", derive(Serialize, Deserialize))]
 pub struct NodeStats {
@@ -193,6 +280,16 @@ pub struct TypedStats {
     pub errors: usize,
 }
 
+impl TypedStats {
+    /// The total size of files, directories and other nodes
+    pub fn size(&self) -> Size {
+        let mut size = self.files.size;
+        size += self.dirs.size;
+        size += self.others.size;
+        size
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, AddAssign)]
 #[cfg_attr(feature = "history", derive(Serialize, Deserialize))]
 pub struct AggStats {
@@ -216,6 +313,8 @@ pub struct ExploreContext {
     pub pending: AtomicUsize,
     pub complete: AtomicUsize,
     pub all_complete: AtomicBool,
+    /// The paths to skip, which are neither explored nor counted
+    pub excludes: Vec<PathBuf>,
 }
 
 impl ExploreContext {
@@ -250,6 +349,16 @@ impl ExploreContext {
     }
 }
 
+impl ExploreContext {
+    /// Returns the pooled copy of a string
+    pub fn intern<S>(&self, str: S) -> StringRef
+    where
+        S: AsRef<str>,
+    {
+        pool_rc(&self.pool, str)
+    }
+}
+
 assert_impl_all!(ExploreContext: Send, Sync);
 
 pub async fn explore(dir: PathBuf, shake: u64, ctx: &ExploreContext) -> Node {
@@ -296,12 +405,23 @@ async fn explore_internal(
 
     let mut futures = vec![];
     while let Some(entry) = read.next().await {
+        if let Ok(entry) = &entry {
+            if ctx.excludes.contains(&entry.path()) {
+                continue;
+            }
+        }
         let node = entry_to_node(shake, entry, ctx);
         futures.push(node);
     }
     ctx.pending.fetch_add(futures.len(), Ordering::Relaxed);
     let children = join_all(futures).await;
 
+    dir_node(shake, name, base_size, children)
+}
+
+/// Builds a directory node, aggregating the stats of its children
+/// and shaking off children with fewer than `shake` bytes of files
+pub fn dir_node(shake: u64, name: StringRef, base_size: Size, children: Vec<Node>) -> Node {
     let mut total = TypedStats::default();
     total.dirs = AggStats {
         count: 1,
@@ -0,0 +1,394 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+use crate::crawl::{FileTypeExt, Node, Size, TypedStats};
+use crate::history;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Grown,
+    Shrunk,
+}
+
+impl ChangeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Grown => "grown",
+            ChangeKind::Shrunk => "shrunk",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// The path of the entry relative to the scanned directory
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    /// The change in real size not covered by the changes of descendants
+    own: i64,
+}
+
+impl Change {
+    /// The change in real size of the entry itself.
+    ///
+    /// For directories, this excludes the changes of descendants listed separately,
+    /// so that a change is not counted again in each of its ancestors.
+    pub fn delta(&self) -> i64 {
+        self.own
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtensionChange {
+    pub fte: FileTypeExt,
+    pub before: TypedStats,
+    pub after: TypedStats,
+}
+
+impl ExtensionChange {
+    /// The change in real size
+    pub fn delta(&self) -> i64 {
+        self.after.size().real as i64 - self.before.size().real as i64
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Changed entries, sorted by the absolute change in real size of the entries themselves
+    pub changes: Vec<Change>,
+    /// Changes in the extension breakdown of the whole tree,
+    /// sorted by the absolute change in real size
+    pub extensions: Vec<ExtensionChange>,
+}
+
+/// Compares two trees.
+///
+/// Entries smaller than the shaking threshold of a report may be missing from it
+/// without having been removed or added.
+/// Such entries are not reported individually,
+/// but their size is still reflected in the change of their parents.
+pub fn diff(before: &history::Report, after: &history::Report) -> Diff {
+    let mut differ = Differ {
+        shake_before: before.meta.shake,
+        shake_after: after.meta.shake,
+        changes: vec![],
+    };
+    differ.compare(&mut PathBuf::new(), &before.tree, &after.tree);
+
+    let mut changes = differ.changes;
+    changes.sort_by_key(|change| Reverse(change.delta().abs()));
+
+    let mut extensions = HashMap::new();
+    for (fte, typed) in before.tree.stats().by_extension {
+        extensions.insert(fte, (typed, TypedStats::default()));
+    }
+    for (fte, typed) in after.tree.stats().by_extension {
+        extensions.entry(fte).or_insert_with(Default::default).1 = typed;
+    }
+    let mut extensions: Vec<_> = extensions
+        .into_iter()
+        .map(|(fte, (before, after))| ExtensionChange { fte, before, after })
+        .filter(|change| change.delta() != 0)
+        .collect();
+    extensions.sort_by_key(|change| Reverse(change.delta().abs()));
+
+    Diff {
+        changes,
+        extensions,
+    }
+}
+
+struct Differ {
+    shake_before: u64,
+    shake_after: u64,
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    /// Records the changes of a node and its descendants, returning its change in real size
+    fn compare(&mut self, path: &mut PathBuf, before: &Node, after: &Node) -> i64 {
+        let size_before = before.total().size();
+        let size_after = after.total().size();
+        let delta = size_after.real as i64 - size_before.real as i64;
+
+        if is_dir(before) != is_dir(after) {
+            self.push(path, ChangeKind::Removed, size_before, Size::default());
+            self.push(path, ChangeKind::Added, Size::default(), size_after);
+            return delta;
+        }
+
+        let own = delta - self.compare_children(path, before, after);
+        if own != 0 {
+            let kind = if own > 0 {
+                ChangeKind::Grown
+            } else {
+                ChangeKind::Shrunk
+            };
+            self.changes.push(Change {
+                path: path.to_path_buf(),
+                kind,
+                own,
+            });
+        }
+        delta
+    }
+
+    /// Records the changes below two directories, returning the change in real size recorded
+    fn compare_children(&mut self, path: &mut PathBuf, before: &Node, after: &Node) -> i64 {
+        let (children_before, children_after) = match (before, after) {
+            (Node::Dir { children: a, .. }, Node::Dir { children: b, .. }) => (a, b),
+            _ => return 0,
+        };
+        let mut recorded = 0;
+
+        let mut map_after: HashMap<&str, &Node> = children_after
+            .iter()
+            .filter_map(|child| child.name().map(|name| (&**name, child)))
+            .collect();
+
+        for child in children_before {
+            let name = match child.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            path.push(&**name);
+            match map_after.remove(&**name) {
+                Some(other) => recorded += self.compare(path, child, other),
+                None => {
+                    if !is_shakable(child, self.shake_after) {
+                        let size = child.total().size();
+                        self.push(path, ChangeKind::Removed, size, Size::default());
+                        recorded -= size.real as i64;
+                    }
+                }
+            }
+            path.pop();
+        }
+
+        for child in children_after {
+            let name = match child.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            if !map_after.contains_key(&**name) {
+                continue; // already compared
+            }
+            if !is_shakable(child, self.shake_before) {
+                path.push(&**name);
+                let size = child.total().size();
+                self.push(path, ChangeKind::Added, Size::default(), size);
+                recorded += size.real as i64;
+                path.pop();
+            }
+        }
+        recorded
+    }
+
+    /// Records an added or removed entry, whose whole change is its own
+    fn push(&mut self, path: &Path, kind: ChangeKind, before: Size, after: Size) {
+        self.changes.push(Change {
+            path: path.to_path_buf(),
+            kind,
+            own: after.real as i64 - before.real as i64,
+        });
+    }
+}
+
+fn is_dir(node: &Node) -> bool {
+    match node {
+        Node::Dir { .. } => true,
+        _ => false,
+    }
+}
+
+/// Checks whether a node would have been hidden by leaf shaking with the threshold `shake`
+fn is_shakable(node: &Node, shake: u64) -> bool {
+    node.total().files.size.real < shake
+}
+
+pub async fn run(args: &cli::DiffArgs, history_dir: &Path, default_root: &Path) -> Result {
+    let path_before =
+        history::resolve(history_dir, &args.namespace, default_root, &args.before).await?;
+    let path_after =
+        history::resolve(history_dir, &args.namespace, default_root, &args.after).await?;
+    let before = history::read(&path_before).await?;
+    let after = history::read(&path_after).await?;
+
+    if before.meta.root != after.meta.root {
+        log::warn!(
+            "Comparing snapshots of different directories: {} and {}",
+            before.meta.root.display(),
+            after.meta.root.display()
+        );
+    }
+
+    let diff = diff(&before, &after);
+    let min_delta = args.min_delta.0 as i64;
+
+    println!("{} -> {}", path_before.display(), path_after.display());
+    println!();
+    for change in diff
+        .changes
+        .iter()
+        .filter(|change| change.delta().abs() >= min_delta)
+        .take(args.limit)
+    {
+        println!(
+            "{:>12}  {:<7}  {}",
+            signed_size(change.delta()),
+            change.kind.name(),
+            display_path(&after.meta.root, &change.path).display()
+        );
+    }
+
+    println!();
+    println!("By extension:");
+    for change in diff
+        .extensions
+        .iter()
+        .filter(|change| change.delta().abs() >= min_delta)
+        .take(args.limit)
+    {
+        println!("{:>12}  {}", signed_size(change.delta()), change.fte);
+    }
+
+    Ok(())
+}
+
+/// Joins a path relative to the scanned directory onto it
+pub fn display_path(root: &Path, path: &Path) -> PathBuf {
+    if path.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(path)
+    }
+}
+
+/// Formats a size difference with an explicit sign
+pub fn signed_size(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, cli::Byte(delta.abs() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawl;
+
+    fn file(name: &str, real: u64) -> Node {
+        Node::File {
+            name: name.into(),
+            extension: "".into(),
+            size: Size {
+                real,
+                content: real,
+            },
+        }
+    }
+
+    fn dir(shake: u64, name: &str, children: Vec<Node>) -> Node {
+        crawl::dir_node(shake, name.into(), Size::default(), children)
+    }
+
+    fn report(shake: u64, tree: Node) -> history::Report {
+        history::Report {
+            meta: history::ReportMeta {
+                timestamp: 0,
+                root: PathBuf::from("/"),
+                host: None,
+                shake,
+                duration_ms: 0,
+            },
+            tree,
+        }
+    }
+
+    fn summarize(diff: &Diff) -> Vec<(&str, ChangeKind, i64)> {
+        diff.changes
+            .iter()
+            .map(|change| (change.path.to_str().unwrap(), change.kind, change.delta()))
+            .collect()
+    }
+
+    #[test]
+    fn ancestors_are_not_reported_again() {
+        let before = dir(
+            0,
+            "/",
+            vec![dir(0, "a", vec![file("x", 100)]), file("b", 10)],
+        );
+        let after = dir(
+            0,
+            "/",
+            vec![dir(0, "a", vec![file("x", 300)]), file("b", 10)],
+        );
+        let diff = diff(&report(0, before), &report(0, after));
+        assert_eq!(summarize(&diff), vec![("a/x", ChangeKind::Grown, 200)]);
+    }
+
+    #[test]
+    fn added_and_removed() {
+        let before = dir(
+            0,
+            "/",
+            vec![dir(0, "a", vec![file("x", 100)]), file("old", 50)],
+        );
+        let after = dir(
+            0,
+            "/",
+            vec![
+                dir(0, "a", vec![file("x", 100), file("y", 30)]),
+                file("new", 70),
+            ],
+        );
+        let diff = diff(&report(0, before), &report(0, after));
+        assert_eq!(
+            summarize(&diff),
+            vec![
+                ("new", ChangeKind::Added, 70),
+                ("old", ChangeKind::Removed, -50),
+                ("a/y", ChangeKind::Added, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn shaken_changes_belong_to_the_parent() {
+        let before = dir(
+            20,
+            "/",
+            vec![dir(20, "a", vec![file("x", 100), file("small", 5)])],
+        );
+        let after = dir(
+            20,
+            "/",
+            vec![dir(20, "a", vec![file("x", 100), file("small", 15)])],
+        );
+        let diff = diff(&report(20, before), &report(20, after));
+        assert_eq!(summarize(&diff), vec![("a", ChangeKind::Grown, 10)]);
+    }
+}
@@ -0,0 +1,291 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use crate::crawl::TypedStats;
+use crate::{cli, history, summary};
+
+mod flame;
+mod html;
+mod json;
+mod ncdu;
+pub mod prometheus;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod table;
+
+/// The formats a history snapshot can be exported in
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// The JSON dump format of ncdu, as written by `ncdu -o`
+    Ncdu,
+    /// Comma-separated values with one row per node
+    Csv,
+    /// Tab-separated values with one row per node
+    Tsv,
+    /// The folded stack format read by flame graph tools
+    Folded,
+    /// An SVG flame graph
+    Flamegraph,
+    /// A self-contained HTML page that views the snapshot without fstree
+    Html,
+    /// JSON for use in scripts
+    Json(JsonMode),
+    /// A summary of the largest entries and changes in Markdown or plain text
+    Summary(summary::Style),
+    /// The Prometheus text exposition format, as read by the node_exporter textfile collector
+    Prometheus,
+    /// A SQLite database, which can store multiple snapshots
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Format {
+    /// The field delimiter of tabular formats
+    fn delimiter(self) -> Option<u8> {
+        match self {
+            Format::Csv => Some(b','),
+            Format::Tsv => Some(b'\t'),
+            _ => None,
+        }
+    }
+
+    /// Whether the format uses the export option with this name
+    pub fn uses_option(self, option: &str) -> bool {
+        match option {
+            "max-depth" | "min-size" => match self {
+                Format::Csv | Format::Tsv | Format::Json(JsonMode::Lines) => true,
+                _ => false,
+            },
+            "extension-summary" => self.delimiter().is_some(),
+            "metrics-depth" | "metrics-path" | "metrics-extensions" => match self {
+                Format::Prometheus => true,
+                _ => false,
+            },
+            "weight" => match self {
+                Format::Folded | Format::Flamegraph => true,
+                _ => false,
+            },
+            "icicle" => match self {
+                Format::Flamegraph => true,
+                _ => false,
+            },
+            "top" => match self {
+                Format::Summary(_) => true,
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "ncdu" => Ok(Format::Ncdu),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "folded" => Ok(Format::Folded),
+            "flamegraph" => Ok(Format::Flamegraph),
+            "html" => Ok(Format::Html),
+            "json" => Ok(Format::Json(JsonMode::Report)),
+            "json-summary" => Ok(Format::Json(JsonMode::Summary)),
+            "jsonl" => Ok(Format::Json(JsonMode::Lines)),
+            "markdown" => Ok(Format::Summary(summary::Style::Markdown)),
+            "text" => Ok(Format::Summary(summary::Style::Text)),
+            "prometheus" => Ok(Format::Prometheus),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Format::Sqlite),
+            _ => Err(format!("Unknown export format {:?}", str)),
+        }
+    }
+}
+
+/// The shapes of JSON output
+#[derive(Debug, Clone, Copy)]
+pub enum JsonMode {
+    /// The whole report with the nested tree
+    Report,
+    /// The metadata and total stats of the report without the tree
+    Summary,
+    /// JSON Lines with one object per node and its full path
+    Lines,
+}
+
+impl FromStr for JsonMode {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "report" => Ok(JsonMode::Report),
+            "summary" => Ok(JsonMode::Summary),
+            "lines" => Ok(JsonMode::Lines),
+            _ => Err(format!("Unknown JSON mode {:?}", str)),
+        }
+    }
+}
+
+/// The quantity that sizes nodes in graphical exports
+#[derive(Debug, Clone, Copy)]
+pub enum Weight {
+    Real,
+    Content,
+    Files,
+}
+
+impl FromStr for Weight {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "real" => Ok(Weight::Real),
+            "content" => Ok(Weight::Content),
+            "files" => Ok(Weight::Files),
+            _ => Err(format!("Unknown weight {:?}", str)),
+        }
+    }
+}
+
+impl Weight {
+    pub fn of(self, stats: &TypedStats) -> u64 {
+        match self {
+            Weight::Real => stats.size().real,
+            Weight::Content => stats.size().content,
+            Weight::Files => stats.files.count as u64,
+        }
+    }
+
+    /// Formats a weight for humans
+    pub fn display(self, weight: u64) -> String {
+        match self {
+            Weight::Real | Weight::Content => cli::Byte(weight).to_string(),
+            Weight::Files => format!("{} files", weight),
+        }
+    }
+}
+
+pub async fn run(args: &cli::ExportArgs, history_dir: &Path, default_root: &Path) -> Result {
+    let path = history::resolve_snapshot(history_dir, &args.snapshot, default_root).await?;
+    let report = history::read(&path).await?;
+
+    let output = args.output.as_ref().map(PathBuf::as_path);
+    match args.format {
+        Format::Ncdu => write_output(output, |w| ncdu::write(&report, w))?,
+        Format::Csv => write_output(output, |w| write_table(&report, b',', args, w))?,
+        Format::Tsv => write_output(output, |w| write_table(&report, b'\t', args, w))?,
+        Format::Folded => write_output(output, |w| flame::write_folded(&report, args.weight, w))?,
+        Format::Flamegraph => write_output(output, |w| {
+            flame::write_svg(&report, args.weight, args.icicle, w)
+        })?,
+        Format::Html => write_output(output, |w| html::write(&report, w))?,
+        Format::Json(mode) => write_json(&report, mode, &table_options(args), output)?,
+        Format::Summary(style) => {
+            // only compare with history if the snapshot is in it,
+            // since listing a directory writes an index into it
+            let namespace = history::namespace_dir(history_dir, &report.meta);
+            let in_history = match (path.parent(), namespace.canonicalize()) {
+                (Some(parent), Ok(namespace)) => parent.canonicalize().ok() == Some(namespace),
+                _ => false,
+            };
+            let previous = if in_history {
+                summary::previous(&namespace, report.meta.timestamp).await
+            } else {
+                None
+            };
+            let options = summary::Options {
+                style,
+                top: args.top,
+            };
+            write_output(output, |w| {
+                summary::write(&report, previous.as_ref(), &options, w)
+            })?
+        }
+        Format::Prometheus => {
+            let options = args.metrics.options();
+            match &args.output {
+                Some(output) => prometheus::write_file(&report, &options, output)?,
+                None => write_output(None, |w| prometheus::write(&report, &options, w))?,
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        Format::Sqlite => {
+            let output = match &args.output {
+                Some(output) => output,
+                None => return Err(make_err("sqlite export requires --output")),
+            };
+            sqlite::write(&report, &path, output)?
+        }
+    }
+
+    // cli::read has rejected --extension-summary for other formats
+    if let (Some(path), Some(delimiter)) = (&args.extension_summary, args.format.delimiter()) {
+        let mut output = BufWriter::new(File::create(path)?);
+        table::write_extensions(&report, delimiter, &mut output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes a report as JSON to a file, or standard output if none is specified
+pub fn write_json(
+    report: &history::Report,
+    mode: JsonMode,
+    options: &table::Options,
+    output: Option<&Path>,
+) -> Result {
+    write_output(output, |w| match mode {
+        JsonMode::Report => json::write(report, w),
+        JsonMode::Summary => json::write_summary(report, w),
+        JsonMode::Lines => json::write_lines(report, options, w),
+    })
+}
+
+/// Writes to the output file, or standard output if none is specified or it is `-`
+fn write_output<F>(output: Option<&Path>, f: F) -> Result
+where
+    F: FnOnce(&mut Box<dyn Write>) -> Result,
+{
+    let stdout = io::stdout();
+    let mut output: Box<dyn Write> = match output.filter(|path| *path != Path::new("-")) {
+        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+        None => Box::new(stdout.lock()),
+    };
+    f(&mut output)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn write_table(
+    report: &history::Report,
+    delimiter: u8,
+    args: &cli::ExportArgs,
+    w: &mut impl Write,
+) -> Result {
+    table::write(report, delimiter, &table_options(args), w)
+}
+
+fn table_options(args: &cli::ExportArgs) -> table::Options {
+    table::Options {
+        max_depth: args.max_depth,
+        min_size: args.min_size.as_ref().map_or(0, |size| size.0),
+    }
+}
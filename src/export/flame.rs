@@ -0,0 +1,238 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::io::Write;
+
+use super::Weight;
+use crate::crawl::{FileTypeExt, Node};
+use crate::history::Report;
+use crate::svg;
+
+const WIDTH: f64 = 1200.;
+const FRAME_HEIGHT: f64 = 16.;
+const FONT_SIZE: f64 = 12.;
+const TITLE_HEIGHT: f64 = 30.;
+/// Frames narrower than this number of pixels are not drawn
+const MIN_WIDTH: f64 = 0.1;
+
+/// Writes the tree in the folded stack format, with one line per leaf and its weight.
+///
+/// The weight of directories not covered by their retained children,
+/// including that of shaken entries, is written as a line of the directory itself.
+pub fn write_folded(report: &Report, weight: Weight, w: &mut impl Write) -> Result {
+    let mut stack = vec![frame_name(&report.meta.root.to_string_lossy())];
+    write_folded_node(&report.tree, weight, &mut stack, w)
+}
+
+fn write_folded_node(
+    node: &Node,
+    weight: Weight,
+    stack: &mut Vec<String>,
+    w: &mut impl Write,
+) -> Result {
+    let mut rest = weight.of(&node.total());
+    if let Node::Dir { children, .. } = node {
+        for child in children {
+            stack.push(frame_name(child.name().map_or("(error)", |name| &**name)));
+            write_folded_node(child, weight, stack, w)?;
+            stack.pop();
+            rest = rest.saturating_sub(weight.of(&child.total()));
+        }
+    }
+    if rest > 0 {
+        writeln!(w, "{} {}", stack.join(";"), rest)?;
+    }
+    Ok(())
+}
+
+/// Replaces the characters that separate frames and weights in the folded stack format
+fn frame_name(name: &str) -> String {
+    name.replace(';', "_").replace('\n', " ")
+}
+
+struct Frame<'t> {
+    node: &'t Node,
+    name: String,
+    path: String,
+    x: f64,
+    width: f64,
+    depth: usize,
+}
+
+/// Writes the tree as an SVG flame graph, or an icicle graph with the root at the top.
+///
+/// Clicking a frame zooms into it, and clicking the root frame zooms out.
+pub fn write_svg(report: &Report, weight: Weight, icicle: bool, w: &mut impl Write) -> Result {
+    let total = weight.of(&report.tree.total());
+    let mut frames = vec![];
+    let root = report.meta.root.to_string_lossy().into_owned();
+    collect_frames(
+        &report.tree,
+        root.clone(),
+        root,
+        0.,
+        WIDTH / total.max(1) as f64,
+        weight,
+        0,
+        &mut frames,
+    );
+    let max_depth = frames.iter().map(|frame| frame.depth).max().unwrap_or(0);
+    let height = TITLE_HEIGHT + (max_depth + 1) as f64 * FRAME_HEIGHT;
+
+    svg::start(w, WIDTH, height)?;
+    writeln!(w, "<script><![CDATA[{}]]></script>", ZOOM_SCRIPT)?;
+    writeln!(
+        w,
+        r#"<text x="{}" y="20" font-size="16" text-anchor="middle">{}</text>"#,
+        WIDTH / 2.,
+        svg::escape(&format!(
+            "{} of {}",
+            if icicle {
+                "Icicle graph"
+            } else {
+                "Flame graph"
+            },
+            report.meta.root.display()
+        ))
+    )?;
+
+    for frame in &frames {
+        let y = if icicle {
+            TITLE_HEIGHT + frame.depth as f64 * FRAME_HEIGHT
+        } else {
+            TITLE_HEIGHT + (max_depth - frame.depth) as f64 * FRAME_HEIGHT
+        };
+        let fill = match frame.node {
+            Node::Dir { .. } => dir_color(frame.depth),
+            node => svg::category_color(&node_category(node)).to_string(),
+        };
+        let node_weight = weight.of(&frame.node.total());
+
+        writeln!(
+            w,
+            r#"<g class="frame" data-x="{:.4}" data-w="{:.4}" data-name="{}" onclick="zoom(this)">"#,
+            frame.x,
+            frame.width,
+            svg::escape(&frame.name)
+        )?;
+        svg::rect(
+            w,
+            (frame.x, y, frame.width, FRAME_HEIGHT),
+            &fill,
+            &format!(
+                "{} ({}, {:.2}%)",
+                frame.path,
+                weight.display(node_weight),
+                node_weight as f64 * 100. / total.max(1) as f64
+            ),
+        )?;
+        writeln!(
+            w,
+            r#"<text x="{:.2}" y="{:.2}" font-size="{}" pointer-events="none">{}</text></g>"#,
+            frame.x + 3.,
+            y + FRAME_HEIGHT - 4.,
+            FONT_SIZE,
+            svg::escape(&svg::fit(&frame.name, frame.width - 6., FONT_SIZE).unwrap_or_default())
+        )?;
+    }
+    svg::end(w)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn collect_frames<'t>(
+    node: &'t Node,
+    name: String,
+    path: String,
+    x: f64,
+    scale: f64,
+    weight: Weight,
+    depth: usize,
+    frames: &mut Vec<Frame<'t>>,
+) {
+    let width = weight.of(&node.total()) as f64 * scale;
+    if width < MIN_WIDTH {
+        return;
+    }
+    frames.push(Frame {
+        node,
+        name,
+        path: path.clone(),
+        x,
+        width,
+        depth,
+    });
+
+    if let Node::Dir { children, .. } = node {
+        let mut children: Vec<&Node> = children.iter().collect();
+        children.sort_by(|a, b| a.name().cmp(&b.name()));
+        let mut x = x;
+        for child in children {
+            let name = child.name().map_or("(error)", |name| &**name).to_string();
+            let path = format!("{}/{}", path.trim_end_matches('/'), name);
+            collect_frames(child, name, path, x, scale, weight, depth + 1, frames);
+            x += weight.of(&child.total()) as f64 * scale;
+        }
+    }
+}
+
+/// Returns the extension category of a leaf node for coloring
+fn node_category(node: &Node) -> FileTypeExt {
+    node.stats()
+        .by_extension
+        .into_iter()
+        .next()
+        .map_or(FileTypeExt::Error, |(fte, _)| fte)
+}
+
+/// Returns a warm color for directories, alternating by depth
+fn dir_color(depth: usize) -> String {
+    let shades = ["#f2a65a", "#eec170", "#f58549", "#f6c177"];
+    shades[depth % shades.len()].to_string()
+}
+
+const ZOOM_SCRIPT: &str = r#"
+var WIDTH = 1200, FONT_SIZE = 12;
+function fit(text, width) {
+    var max = Math.floor(width / (FONT_SIZE * 0.6));
+    if (text.length <= max) return text;
+    return max >= 4 ? text.substring(0, max - 2) + ".." : "";
+}
+function zoom(target) {
+    var zx = +target.dataset.x, scale = WIDTH / +target.dataset.w;
+    var frames = document.querySelectorAll("g.frame");
+    for (var i = 0; i < frames.length; i++) {
+        var frame = frames[i];
+        var x = (+frame.dataset.x - zx) * scale, w = +frame.dataset.w * scale;
+        if (x + w <= 0.01 || x >= WIDTH - 0.01) {
+            frame.style.display = "none";
+            continue;
+        }
+        if (x < 0) {
+            w += x;
+            x = 0;
+        }
+        w = Math.min(w, WIDTH - x);
+        frame.style.display = "";
+        var rect = frame.querySelector("rect"), text = frame.querySelector("text");
+        rect.setAttribute("x", x);
+        rect.setAttribute("width", w);
+        text.setAttribute("x", x + 3);
+        text.textContent = fit(frame.dataset.name, w - 6);
+    }
+}
+"#;
@@ -0,0 +1,45 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+
+use crate::history::Report;
+
+const TEMPLATE: &str = include_str!("../../web/report.html");
+const PLACEHOLDER: &str = "{{REPORT}}";
+
+/// Writes a self-contained HTML page that views a report offline.
+///
+/// The report is embedded as gzip-compressed JSON in base64.
+/// The page decompresses it with the `DecompressionStream` browser API where available,
+/// and with a small inflate implementation in the page otherwise.
+pub fn write(report: &Report, w: &mut impl Write) -> Result {
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::best());
+    serde_json::to_writer(&mut encoder, report).map_err(make_err)?;
+    let data = base64::encode(&encoder.finish()?);
+
+    let split = TEMPLATE
+        .find(PLACEHOLDER)
+        .expect("Report template has no placeholder");
+    w.write_all(TEMPLATE[..split].as_bytes())?;
+    w.write_all(data.as_bytes())?;
+    w.write_all(TEMPLATE[split + PLACEHOLDER.len()..].as_bytes())?;
+    Ok(())
+}
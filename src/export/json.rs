@@ -0,0 +1,146 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::table;
+use crate::crawl::{Node, TypedStats};
+use crate::history::{Report, ReportMeta};
+
+/// Writes the whole report, including the nested tree, as a single JSON document
+pub fn write(report: &Report, w: &mut impl Write) -> Result {
+    serde_json::to_writer(&mut *w, report).map_err(make_err)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    #[serde(flatten)]
+    meta: &'a ReportMeta,
+    total: TypedStats,
+    extensions: Vec<Extension>,
+}
+
+#[derive(Serialize)]
+struct Extension {
+    extension: String,
+    count: usize,
+    real_size: u64,
+    content_size: u64,
+    errors: usize,
+}
+
+/// Writes the metadata and total stats of a report without the tree,
+/// with the extensions sorted by real size in descending order
+pub fn write_summary(report: &Report, w: &mut impl Write) -> Result {
+    let stats = report.tree.stats();
+    let mut extensions: Vec<Extension> = stats
+        .by_extension
+        .into_iter()
+        .map(|(fte, typed)| Extension {
+            extension: fte.to_string(),
+            count: typed.files.count + typed.dirs.count + typed.others.count,
+            real_size: typed.size().real,
+            content_size: typed.size().content,
+            errors: typed.errors,
+        })
+        .collect();
+    extensions.sort_by_key(|extension| Reverse(extension.real_size));
+
+    let summary = Summary {
+        meta: &report.meta,
+        total: stats.total,
+        extensions,
+    };
+    serde_json::to_writer(&mut *w, &summary).map_err(make_err)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    path: &'a Path,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    depth: usize,
+    real_size: u64,
+    content_size: u64,
+    files: usize,
+    errors: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dominant_extension: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Writes one JSON object per line for each node retained in a report, in depth-first order.
+///
+/// Like the rows of the table formats, nodes removed by leaf shaking
+/// are only counted in the lines of their ancestors.
+pub fn write_lines(report: &Report, options: &table::Options, w: &mut impl Write) -> Result {
+    write_node(&report.tree, &report.meta.root, 0, options, w)
+}
+
+fn write_node(
+    node: &Node,
+    path: &Path,
+    depth: usize,
+    options: &table::Options,
+    w: &mut impl Write,
+) -> Result {
+    let total = node.total();
+    let size = total.size();
+    if size.real < options.min_size {
+        return Ok(());
+    }
+
+    let line = Line {
+        path,
+        kind: node.kind(),
+        depth,
+        real_size: size.real,
+        content_size: size.content,
+        files: total.files.count,
+        errors: total.errors,
+        dominant_extension: table::dominant_extension(node).map(|fte| fte.to_string()),
+        error: match node {
+            Node::Error { error, .. } => Some(error.to_string()),
+            _ => None,
+        },
+    };
+    serde_json::to_writer(&mut *w, &line).map_err(make_err)?;
+    writeln!(w)?;
+
+    if let Node::Dir { children, .. } = node {
+        if options.max_depth.map_or(true, |max| depth < max) {
+            for child in children {
+                let path = match child.name() {
+                    Some(name) => path.join(&**name),
+                    None => path.to_path_buf(),
+                };
+                write_node(child, &path, depth + 1, options, w)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,267 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::crawl::{Node, Size, TypedStats};
+use crate::history::Report;
+
+/// The major and minor versions of the ncdu dump format written
+const MAJOR_VERSION: u32 = 1;
+const MINOR_VERSION: u32 = 0;
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    progname: &'a str,
+    progver: &'a str,
+    timestamp: i64,
+}
+
+#[derive(Default, Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dsize: Option<u64>,
+    #[serde(skip_serializing_if = "is_false")]
+    notreg: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    read_error: bool,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl<'a> Entry<'a> {
+    fn sized(name: &'a str, size: Size) -> Self {
+        Self {
+            name,
+            asize: Some(size.content),
+            dsize: Some(size.real),
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes a report in the ncdu JSON dump format.
+///
+/// ncdu computes directory sizes from their entries,
+/// so the entries removed by leaf shaking are written as a single placeholder file,
+/// and the shaken subdirectories are counted into the size of their parent.
+pub fn write(report: &Report, w: &mut impl Write) -> Result {
+    let metadata = Metadata {
+        progname: env!("CARGO_PKG_NAME"),
+        progver: env!("CARGO_PKG_VERSION"),
+        timestamp: report.meta.timestamp,
+    };
+    write!(w, "[{},{},", MAJOR_VERSION, MINOR_VERSION)?;
+    serde_json::to_writer(&mut *w, &metadata).map_err(make_err)?;
+    write!(w, ",")?;
+
+    let root = report.meta.root.to_string_lossy();
+    write_node(&report.tree, Some(&root), w)?;
+    writeln!(w, "]")?;
+    Ok(())
+}
+
+fn write_node(node: &Node, name_override: Option<&str>, w: &mut impl Write) -> Result {
+    let name = name_override
+        .or_else(|| node.name().map(|name| &**name))
+        .unwrap_or("");
+
+    let entry = match node {
+        Node::Dir {
+            children, stats, ..
+        } => {
+            let mut rest = stats.total;
+            for child in children {
+                subtract(&mut rest, &child.total());
+            }
+
+            write!(w, "[")?;
+            write_entry(&Entry::sized(name, rest.dirs.size), w)?;
+            for child in children {
+                write!(w, ",")?;
+                write_node(child, None, w)?;
+            }
+            let shaken = rest.files.count + rest.others.count;
+            if shaken > 0 {
+                let mut size = rest.files.size;
+                size += rest.others.size;
+                let name = format!("({} shaken entries)", shaken);
+                write!(w, ",")?;
+                write_entry(&Entry::sized(&name, size), w)?;
+            }
+            write!(w, "]")?;
+            return Ok(());
+        }
+        Node::File { size, .. } => Entry::sized(name, *size),
+        Node::Link { size, .. }
+        | Node::BlockDevice { size, .. }
+        | Node::CharDevice { size, .. }
+        | Node::NamedPipe { size, .. }
+        | Node::UnixSocket { size, .. }
+        | Node::Other { size, .. } => Entry {
+            notreg: true,
+            ..Entry::sized(name, *size)
+        },
+        Node::Error { .. } => Entry {
+            name,
+            read_error: true,
+            ..Default::default()
+        },
+    };
+    write_entry(&entry, w)
+}
+
+fn write_entry(entry: &Entry, w: &mut impl Write) -> Result {
+    serde_json::to_writer(w, entry).map_err(make_err)
+}
+
+/// Subtracts the stats of a child from the remaining stats of its parent
+fn subtract(rest: &mut TypedStats, child: &TypedStats) {
+    fn sub(rest: &mut Size, child: &Size) {
+        rest.real = rest.real.saturating_sub(child.real);
+        rest.content = rest.content.saturating_sub(child.content);
+    }
+    rest.files.count = rest.files.count.saturating_sub(child.files.count);
+    sub(&mut rest.files.size, &child.files.size);
+    rest.dirs.count = rest.dirs.count.saturating_sub(child.dirs.count);
+    sub(&mut rest.dirs.size, &child.dirs.size);
+    rest.others.count = rest.others.count.saturating_sub(child.others.count);
+    sub(&mut rest.others.size, &child.others.size);
+    rest.errors = rest.errors.saturating_sub(child.errors);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    use crate::crawl::{self, ExploreContext};
+    use crate::history::ReportMeta;
+    use crate::import;
+
+    fn size(real: u64, content: u64) -> Size {
+        Size { real, content }
+    }
+
+    fn file(name: &str, size: Size) -> Node {
+        Node::File {
+            name: name.into(),
+            extension: "".into(),
+            size,
+        }
+    }
+
+    fn report(tree: Node) -> Report {
+        Report {
+            meta: ReportMeta {
+                timestamp: 1_600_000_000,
+                root: PathBuf::from("/data"),
+                host: None,
+                shake: 0,
+                duration_ms: 0,
+            },
+            tree,
+        }
+    }
+
+    /// Exports a report and imports it back
+    fn round_trip(report: &Report) -> import::Imported {
+        let mut data = vec![];
+        write(report, &mut data).unwrap();
+        import::ncdu::read(&data, 0, &ExploreContext::default()).unwrap()
+    }
+
+    #[test]
+    fn round_trip_keeps_sizes() {
+        let tree = crawl::dir_node(
+            0,
+            "data".into(),
+            size(4096, 4096),
+            vec![
+                crawl::dir_node(
+                    0,
+                    "a".into(),
+                    size(4096, 4096),
+                    vec![file("x.log", size(4096, 4000)), file("y", size(4096, 100))],
+                ),
+                file("b", size(4096, 50)),
+                Node::Other {
+                    name: "fifo".into(),
+                    extension: "".into(),
+                    size: size(0, 0),
+                },
+                Node::Error {
+                    name: Some("locked".into()),
+                    error: "Permission denied".to_string().into(),
+                },
+            ],
+        );
+        let report = report(tree);
+        let imported = round_trip(&report);
+        assert_eq!(imported.root, Some(PathBuf::from("/data")));
+        assert_eq!(imported.timestamp, Some(1_600_000_000));
+
+        let before = report.tree.total();
+        let after = imported.tree.total();
+        assert_eq!(after.size().real, before.size().real);
+        assert_eq!(after.size().content, before.size().content);
+        assert_eq!(after.files.count, before.files.count);
+        assert_eq!(after.dirs.count, before.dirs.count);
+        assert_eq!(after.others.count, before.others.count);
+        assert_eq!(after.errors, before.errors);
+
+        let x = imported.tree.find(Path::new("a/x.log")).unwrap();
+        assert_eq!(x.total().size().content, 4000);
+    }
+
+    #[test]
+    fn round_trip_keeps_shaken_sizes() {
+        let shake = 1000;
+        let tree = crawl::dir_node(
+            shake,
+            "data".into(),
+            size(4096, 4096),
+            vec![
+                file("big", size(8192, 5000)),
+                file("small", size(512, 10)),
+                crawl::dir_node(
+                    shake,
+                    "tiny".into(),
+                    size(4096, 4096),
+                    vec![file("z", size(512, 20))],
+                ),
+            ],
+        );
+        let report = report(tree);
+        let imported = round_trip(&report);
+
+        let before = report.tree.total().size();
+        let after = imported.tree.total().size();
+        assert_eq!(after.real, before.real);
+        assert_eq!(after.content, before.content);
+        assert!(imported.tree.find(Path::new("tiny")).is_none());
+    }
+}
@@ -0,0 +1,220 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::crawl::Node;
+use crate::history::Report;
+
+/// Selects the metrics written
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Write the sizes of directories up to this depth below the scanned directory
+    pub depth: usize,
+    /// Write the sizes of only these directories relative to the scanned directory instead
+    pub paths: Vec<PathBuf>,
+    /// Write the totals of this number of largest extensions
+    pub extensions: usize,
+}
+
+/// Writes the metrics of a report to a file atomically,
+/// so that the node_exporter textfile collector never reads a partial file
+pub fn write_file(report: &Report, options: &Options, path: &Path) -> Result {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| make_err(format!("{}: not a file", path.display())))?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = (|| -> Result {
+        let mut f = BufWriter::new(File::create(&temp_path)?);
+        write(report, options, &mut f)?;
+        let f = f.into_inner().map_err(|err| make_err(err.to_string()))?;
+        f.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        drop(fs::remove_file(&temp_path));
+    }
+    result
+}
+
+/// Writes the metrics of a report in the Prometheus text exposition format
+pub fn write(report: &Report, options: &Options, w: &mut impl Write) -> Result {
+    let root = report.meta.root.to_string_lossy();
+    let root_label = format!("root=\"{}\"", escape(&root));
+
+    let mut dirs = vec![];
+    if options.paths.is_empty() {
+        collect_dirs(&report.tree, &report.meta.root, options.depth, &mut dirs);
+    } else {
+        dirs.push((report.meta.root.clone(), &report.tree));
+        for path in &options.paths {
+            match report.tree.find(path) {
+                Some(node @ Node::Dir { .. }) => dirs.push((report.meta.root.join(path), node)),
+                _ => log::warn!("{} is not a directory in the report", path.display()),
+            }
+        }
+    }
+
+    write_dirs(
+        w,
+        "fstree_directory_size_bytes",
+        "Real size of a directory and its contents",
+        &root_label,
+        &dirs,
+        |node| node.total().size().real,
+    )?;
+    write_dirs(
+        w,
+        "fstree_directory_content_size_bytes",
+        "Content size of a directory and its contents",
+        &root_label,
+        &dirs,
+        |node| node.total().size().content,
+    )?;
+    write_dirs(
+        w,
+        "fstree_directory_files",
+        "Number of files in a directory and its subdirectories",
+        &root_label,
+        &dirs,
+        |node| node.total().files.count as u64,
+    )?;
+
+    let mut extensions = report.tree.stats().by_extension;
+    extensions.sort_by_key(|(_, typed)| Reverse(typed.size().real));
+    extensions.truncate(options.extensions);
+    header(
+        w,
+        "fstree_extension_size_bytes",
+        "Real size of all nodes with an extension",
+    )?;
+    for (fte, typed) in &extensions {
+        writeln!(
+            w,
+            "fstree_extension_size_bytes{{{},extension=\"{}\"}} {}",
+            root_label,
+            escape(&fte.to_string()),
+            typed.size().real
+        )?;
+    }
+    header(
+        w,
+        "fstree_extension_count",
+        "Number of nodes with an extension",
+    )?;
+    for (fte, typed) in &extensions {
+        writeln!(
+            w,
+            "fstree_extension_count{{{},extension=\"{}\"}} {}",
+            root_label,
+            escape(&fte.to_string()),
+            typed.files.count + typed.dirs.count + typed.others.count
+        )?;
+    }
+
+    let total = report.tree.total();
+    header(
+        w,
+        "fstree_errors",
+        "Number of nodes that could not be scanned",
+    )?;
+    writeln!(w, "fstree_errors{{{}}} {}", root_label, total.errors)?;
+    header(w, "fstree_scan_duration_seconds", "Time taken by the scan")?;
+    writeln!(
+        w,
+        "fstree_scan_duration_seconds{{{}}} {}",
+        root_label,
+        report.meta.duration_ms as f64 / 1000.
+    )?;
+    header(
+        w,
+        "fstree_scan_timestamp_seconds",
+        "Time the scan was started",
+    )?;
+    writeln!(
+        w,
+        "fstree_scan_timestamp_seconds{{{}}} {}",
+        root_label, report.meta.timestamp
+    )?;
+    Ok(())
+}
+
+fn write_dirs<F>(
+    w: &mut impl Write,
+    name: &str,
+    help: &str,
+    root_label: &str,
+    dirs: &[(PathBuf, &Node)],
+    value: F,
+) -> Result
+where
+    F: Fn(&Node) -> u64,
+{
+    header(w, name, help)?;
+    for (path, node) in dirs {
+        writeln!(
+            w,
+            "{}{{{},path=\"{}\"}} {}",
+            name,
+            root_label,
+            escape(&path.to_string_lossy()),
+            value(node)
+        )?;
+    }
+    Ok(())
+}
+
+fn header(w: &mut impl Write, name: &str, help: &str) -> Result {
+    writeln!(w, "# HELP {} {}", name, help)?;
+    writeln!(w, "# TYPE {} gauge", name)?;
+    Ok(())
+}
+
+/// Collects the directories up to `depth` levels below `node` in depth-first order
+fn collect_dirs<'t>(
+    node: &'t Node,
+    path: &Path,
+    depth: usize,
+    dirs: &mut Vec<(PathBuf, &'t Node)>,
+) {
+    if let Node::Dir { children, .. } = node {
+        dirs.push((path.to_path_buf(), node));
+        if depth > 0 {
+            for child in children {
+                if let Some(name) = child.name() {
+                    collect_dirs(child, &path.join(&**name), depth - 1, dirs);
+                }
+            }
+        }
+    }
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,219 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::Path;
+
+use rusqlite::{params, Connection, Transaction};
+
+use crate::crawl::{FileTypeExt, Node};
+use crate::history::Report;
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+
+CREATE TABLE IF NOT EXISTS reports (
+    id INTEGER PRIMARY KEY,
+    file TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    root TEXT NOT NULL,
+    host TEXT,
+    shake INTEGER NOT NULL,
+    duration_ms INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS reports_snapshot ON reports (root, host, timestamp);
+
+CREATE TABLE IF NOT EXISTS nodes (
+    report_id INTEGER NOT NULL REFERENCES reports (id) ON DELETE CASCADE,
+    id INTEGER NOT NULL,
+    parent_id INTEGER,
+    name TEXT,
+    path TEXT NOT NULL,
+    type TEXT NOT NULL,
+    depth INTEGER NOT NULL,
+    real_size INTEGER NOT NULL,
+    content_size INTEGER NOT NULL,
+    files INTEGER NOT NULL,
+    dirs INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    PRIMARY KEY (report_id, id)
+);
+CREATE INDEX IF NOT EXISTS nodes_parent ON nodes (report_id, parent_id);
+CREATE INDEX IF NOT EXISTS nodes_path ON nodes (report_id, path);
+CREATE INDEX IF NOT EXISTS nodes_size ON nodes (report_id, type, real_size);
+
+CREATE TABLE IF NOT EXISTS node_extensions (
+    report_id INTEGER NOT NULL,
+    node_id INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    extension TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    real_size INTEGER NOT NULL,
+    content_size INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    PRIMARY KEY (report_id, node_id, type, extension),
+    FOREIGN KEY (report_id, node_id) REFERENCES nodes (report_id, id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS node_extensions_size
+    ON node_extensions (report_id, extension, real_size);
+";
+
+/// Adds a report to a SQLite database, creating the tables if they do not exist.
+///
+/// Each report gets a row in `reports`, and the rows of the other tables are keyed by its id,
+/// so multiple snapshots can share a database.
+/// Exporting a snapshot again replaces its previous rows.
+/// Extension stats are only stored for directories,
+/// since the stats of other nodes are the node itself.
+pub fn write(report: &Report, file: &Path, db: &Path) -> Result {
+    let mut conn = Connection::open(db).map_err(make_err)?;
+    conn.execute_batch(SCHEMA).map_err(make_err)?;
+
+    let tx = conn.transaction().map_err(make_err)?;
+    let id = insert_report(&tx, report, file).map_err(make_err)?;
+    let mut next_id = 0;
+    insert_node(
+        &tx,
+        id,
+        &report.tree,
+        None,
+        &report.meta.root,
+        0,
+        &mut next_id,
+    )
+    .map_err(make_err)?;
+    tx.commit().map_err(make_err)?;
+
+    log::info!(
+        "Exported {} nodes to {} as report {}",
+        next_id,
+        db.display(),
+        id
+    );
+    Ok(())
+}
+
+fn insert_report(tx: &Transaction, report: &Report, file: &Path) -> rusqlite::Result<i64> {
+    let meta = &report.meta;
+    let root = meta.root.to_string_lossy().into_owned();
+    tx.execute(
+        "DELETE FROM reports WHERE root = ? AND host IS ? AND timestamp = ?",
+        params![root, meta.host, meta.timestamp],
+    )?;
+    tx.execute(
+        "INSERT INTO reports (file, timestamp, root, host, shake, duration_ms)
+            VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            file.file_name()
+                .unwrap_or_else(|| file.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            meta.timestamp,
+            root,
+            meta.host,
+            meta.shake as i64,
+            meta.duration_ms as i64,
+        ],
+    )?;
+    Ok(tx.last_insert_rowid())
+}
+
+fn insert_node(
+    tx: &Transaction,
+    report_id: i64,
+    node: &Node,
+    parent_id: Option<i64>,
+    path: &Path,
+    depth: i64,
+    next_id: &mut i64,
+) -> rusqlite::Result<()> {
+    let id = *next_id;
+    *next_id += 1;
+
+    let total = node.total();
+    let size = total.size();
+    tx.prepare_cached(
+        "INSERT INTO nodes (report_id, id, parent_id, name, path, type, depth,
+                real_size, content_size, files, dirs, errors)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )?
+    .execute(params![
+        report_id,
+        id,
+        parent_id,
+        node.name().map(|name| &**name),
+        path.to_string_lossy().into_owned(),
+        node.kind(),
+        depth,
+        size.real as i64,
+        size.content as i64,
+        total.files.count as i64,
+        total.dirs.count as i64,
+        total.errors as i64,
+    ])?;
+
+    if let Node::Dir {
+        children, stats, ..
+    } = node
+    {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO node_extensions (report_id, node_id, type, extension,
+                    count, real_size, content_size, errors)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )?;
+        for (fte, typed) in &stats.by_extension {
+            let (kind, extension) = split_extension(fte);
+            let size = typed.size();
+            let count = typed.files.count + typed.dirs.count + typed.others.count;
+            insert.execute(params![
+                report_id,
+                id,
+                kind,
+                extension,
+                count as i64,
+                size.real as i64,
+                size.content as i64,
+                typed.errors as i64,
+            ])?;
+        }
+        drop(insert);
+
+        for child in children {
+            let path = match child.name() {
+                Some(name) => path.join(&**name),
+                None => path.to_path_buf(),
+            };
+            insert_node(tx, report_id, child, Some(id), &path, depth + 1, next_id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Splits an extension into the type of nodes and the extension without the dot
+fn split_extension(fte: &FileTypeExt) -> (&'static str, &str) {
+    match fte {
+        FileTypeExt::File(ext) => ("file", &**ext),
+        FileTypeExt::Dir => ("dir", ""),
+        FileTypeExt::Link => ("link", ""),
+        FileTypeExt::BlockDevice => ("block_device", ""),
+        FileTypeExt::CharDevice => ("char_device", ""),
+        FileTypeExt::NamedPipe => ("named_pipe", ""),
+        FileTypeExt::UnixSocket => ("socket", ""),
+        FileTypeExt::Error => ("error", ""),
+        FileTypeExt::Other(ext) => ("other", &**ext),
+    }
+}
@@ -0,0 +1,142 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::io::Write;
+use std::path::Path;
+
+use csv::WriterBuilder;
+
+use crate::crawl::{FileTypeExt, Node};
+use crate::history::Report;
+
+/// Selects the nodes written as rows
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Skip nodes deeper than this below the scanned directory
+    pub max_depth: Option<usize>,
+    /// Skip nodes with a smaller real size than this
+    pub min_size: u64,
+}
+
+/// Writes one row per node retained in a report, in depth-first order.
+///
+/// Nodes removed by leaf shaking are only counted in the rows of their ancestors.
+pub fn write(report: &Report, delimiter: u8, options: &Options, w: &mut impl Write) -> Result {
+    let mut writer = WriterBuilder::new().delimiter(delimiter).from_writer(w);
+    writer
+        .write_record(&[
+            "path",
+            "type",
+            "depth",
+            "real_size",
+            "content_size",
+            "files",
+            "errors",
+            "dominant_extension",
+        ])
+        .map_err(make_err)?;
+    write_node(&mut writer, &report.tree, &report.meta.root, 0, options)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_node<W: Write>(
+    writer: &mut csv::Writer<W>,
+    node: &Node,
+    path: &Path,
+    depth: usize,
+    options: &Options,
+) -> Result {
+    let total = node.total();
+    let size = total.size();
+    if size.real < options.min_size {
+        return Ok(());
+    }
+
+    writer
+        .write_record(&[
+            path.to_string_lossy().into_owned(),
+            node.kind().to_string(),
+            depth.to_string(),
+            size.real.to_string(),
+            size.content.to_string(),
+            total.files.count.to_string(),
+            total.errors.to_string(),
+            dominant_extension(node).map_or_else(String::new, |fte| fte.to_string()),
+        ])
+        .map_err(make_err)?;
+
+    if let Node::Dir { children, .. } = node {
+        if options.max_depth.map_or(true, |max| depth < max) {
+            for child in children {
+                let path = match child.name() {
+                    Some(name) => path.join(&**name),
+                    None => path.to_path_buf(),
+                };
+                write_node(writer, child, &path, depth + 1, options)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the extension taking the most real size in a node, ignoring the directories themselves
+pub fn dominant_extension(node: &Node) -> Option<FileTypeExt> {
+    match node {
+        Node::Dir { stats, .. } => stats
+            .by_extension
+            .iter()
+            .filter(|(fte, _)| *fte != FileTypeExt::Dir)
+            .max_by_key(|(_, typed)| typed.size().real)
+            .map(|(fte, _)| fte.clone()),
+        _ => node
+            .stats()
+            .by_extension
+            .into_iter()
+            .next()
+            .map(|(fte, _)| fte),
+    }
+}
+
+/// Writes one row per extension with its total stats in the scanned directory,
+/// sorted by real size in descending order
+pub fn write_extensions(report: &Report, delimiter: u8, w: &mut impl Write) -> Result {
+    let mut writer = WriterBuilder::new().delimiter(delimiter).from_writer(w);
+    writer
+        .write_record(&["extension", "count", "real_size", "content_size", "errors"])
+        .map_err(make_err)?;
+
+    let mut extensions = report.tree.stats().by_extension;
+    extensions.sort_by_key(|(_, typed)| Reverse(typed.size().real));
+    for (fte, typed) in extensions {
+        let size = typed.size();
+        let count = typed.files.count + typed.dirs.count + typed.others.count;
+        writer
+            .write_record(&[
+                fte.to_string(),
+                count.to_string(),
+                size.real.to_string(),
+                size.content.to_string(),
+                typed.errors.to_string(),
+            ])
+            .map_err(make_err)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
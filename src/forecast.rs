@@ -0,0 +1,194 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::history;
+use crate::trend::{self, Fit};
+
+const DAY: f64 = 86400.0;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+    /// Ordinary least squares
+    Linear,
+    /// The Theil-Sen estimator
+    Robust,
+}
+
+impl FromStr for Method {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "linear" => Ok(Method::Linear),
+            "robust" => Ok(Method::Robust),
+            _ => Err(format!("Unknown regression method {:?}", str)),
+        }
+    }
+}
+
+impl Method {
+    fn fit(self, points: &[(f64, f64)]) -> Option<Fit> {
+        match self {
+            Method::Linear => trend::linear_fit(points),
+            Method::Robust => trend::theil_sen_fit(points),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub method: Method,
+    /// Only snapshots within this number of days before the latest one are used
+    pub window_days: u32,
+    /// The number of largest children of the scanned directory to forecast for
+    pub dirs: usize,
+}
+
+/// The same defaults as the command line options
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            method: Method::Robust,
+            window_days: 30,
+            dirs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Forecast {
+    pub path: PathBuf,
+    /// The real size of the path in the latest snapshot
+    pub size: u64,
+    /// The space currently available on the filesystem of the path
+    pub available: u64,
+    /// The capacity of the filesystem of the path
+    pub capacity: u64,
+    /// The growth rate of the path in bytes per day
+    pub rate: f64,
+    /// The number of days until the filesystem is full if only this path keeps growing,
+    /// or `None` if the path is not growing
+    pub days: Option<f64>,
+}
+
+impl Forecast {
+    fn new(path: PathBuf, size: u64, fs: &fs2::FsStats, fit: Fit) -> Self {
+        let rate = fit.slope * DAY;
+        let available = fs.available_space();
+        Self {
+            path,
+            size,
+            available,
+            capacity: fs.total_space(),
+            rate,
+            days: if rate > 0.0 {
+                Some(available as f64 / rate)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl fmt::Display for Forecast {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.days {
+            Some(days) => write!(
+                f,
+                "at current rate {} fills in ~{:.0} days ({})",
+                self.path.display(),
+                days.ceil(),
+                trend::daily_rate(self.rate / DAY)
+            ),
+            None => write!(
+                f,
+                "{} is not growing ({})",
+                self.path.display(),
+                trend::daily_rate(self.rate / DAY)
+            ),
+        }
+    }
+}
+
+/// Forecasts when the filesystem of the scanned directory of a history namespace fills up,
+/// followed by the forecasts for its largest children.
+pub async fn namespace(dir: &Path, options: &Options) -> Result<Vec<Forecast>> {
+    let entries = history::list(dir).await?;
+    let latest = match entries.last() {
+        Some(latest) => latest,
+        None => return Ok(vec![]),
+    };
+    let fs = match fs2::statvfs(&latest.root) {
+        Ok(fs) => fs,
+        Err(err) => {
+            log::warn!("Cannot forecast for {}: {}", latest.root.display(), err);
+            return Ok(vec![]);
+        }
+    };
+    let since = latest.timestamp - i64::from(options.window_days) * DAY as i64;
+
+    let mut forecasts = vec![];
+
+    let points: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.timestamp >= since && entry.root == latest.root)
+        .map(|entry| (entry.timestamp as f64, entry.size.real as f64))
+        .collect();
+    if let Some(fit) = options.method.fit(&points) {
+        forecasts.push(Forecast::new(
+            latest.root.clone(),
+            latest.size.real,
+            &fs,
+            fit,
+        ));
+    }
+
+    if options.dirs == 0 {
+        return Ok(forecasts);
+    }
+    let series = history::path_series(dir, &latest.root, since).await?;
+    let children = match series.iter().rev().find_map(|point| point.stats.as_ref()) {
+        Some(stats) => &stats.children,
+        None => return Ok(forecasts),
+    };
+    for (name, size) in children.iter().take(options.dirs) {
+        let points: Vec<_> = series
+            .iter()
+            .filter_map(|point| {
+                let (_, size) = point
+                    .stats
+                    .as_ref()?
+                    .children
+                    .iter()
+                    .find(|(child, _)| child == name)?;
+                Some((point.entry.timestamp as f64, size.real as f64))
+            })
+            .collect();
+        if let Some(fit) = options.method.fit(&points) {
+            forecasts.push(Forecast::new(latest.root.join(name), size.real, &fs, fit));
+        }
+    }
+
+    Ok(forecasts)
+}
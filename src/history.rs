@@ -16,50 +16,337 @@
 #[allow(unused_imports)]
 use crate::result::{make_err, Result};
 
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
 
 use chrono::offset::Local as LocalTz;
-use count_write::CountWrite;
-use flate2::{Compression, GzBuilder};
+use chrono::{NaiveDateTime, TimeZone};
+use flate2::GzBuilder;
 use futures_util::stream::StreamExt;
-use serde::Serialize;
-use serde_json::ser::{PrettyFormatter, Serializer};
+use serde::{Deserialize, Serialize};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
 
+use crate::cli;
 use crate::crawl;
 
-pub async fn write(tree: &crawl::Node, dir: &PathBuf) -> Result {
+mod cache;
+mod format;
+mod index;
+mod legacy;
+mod lock;
+mod namespace;
+mod retention;
+
+pub use cache::{path_series, PathStats, SeriesPoint};
+pub use format::{Compression, Format};
+pub use index::IndexEntry;
+use legacy::Origin;
+use retention::Candidate;
+pub use retention::Policy;
+
+/// The version of the schema of history files written by this version of fstree.
+///
+/// This must be incremented whenever the serialized form of `Report` changes,
+/// with the previous definitions frozen in the `legacy` module.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const FILE_NAME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+/// The length of a date formatted with `FILE_NAME_FORMAT`
+const FILE_NAME_DATE_LEN: usize = 19;
+const TEMP_FILE_PREFIX: &str = ".tmp-";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub meta: ReportMeta,
+    pub tree: crawl::Node,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportMeta {
+    /// The time the scan was started, in seconds since the Unix epoch
+    pub timestamp: i64,
+    /// The canonical path of the scanned directory
+    pub root: PathBuf,
+    /// The hostname of the scanning machine, if it could be determined
+    pub host: Option<String>,
+    /// The leaf shaking threshold used in the scan
+    pub shake: u64,
+    /// The time taken by the scan, in milliseconds
+    pub duration_ms: u64,
+}
+
+impl ReportMeta {
+    pub fn new(timestamp: i64, root: PathBuf, shake: u64, duration: Duration) -> Self {
+        Self {
+            timestamp,
+            root,
+            host: local_host(),
+            shake,
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
+
+/// Returns the hostname of this machine, if it can be determined
+pub fn local_host() -> Option<String> {
+    match hostname::get() {
+        Ok(host) => Some(host.to_string_lossy().into_owned()),
+        Err(err) => {
+            log::warn!("Failed to get hostname: {}", err);
+            None
+        }
+    }
+}
+
+/// Returns the directory storing the history of the root and host of a report
+pub fn namespace_dir(history_dir: &Path, meta: &ReportMeta) -> PathBuf {
+    namespace::dir(
+        history_dir,
+        &meta.root,
+        meta.host.as_ref().map(String::as_str),
+    )
+}
+
+/// Returns the directory storing the history of a canonical root scanned on this machine
+pub fn local_namespace_dir(history_dir: &Path, root: &Path) -> PathBuf {
+    let host = local_host();
+    namespace::dir(history_dir, root, host.as_ref().map(String::as_str))
+}
+
+/// Returns the namespace directories selected by the command line arguments
+pub async fn select_namespaces(
+    history_dir: &Path,
+    args: &cli::NamespaceArgs,
+    default_root: &Path,
+) -> Result<Vec<PathBuf>> {
+    if !history_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    if args.all_namespaces {
+        return namespace::all(history_dir).await;
+    }
+
+    let root = args.root.as_ref().map_or(default_root, PathBuf::as_path);
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let dir = match &args.host {
+        Some(host) => namespace::dir(history_dir, &root, Some(host)),
+        None => local_namespace_dir(history_dir, &root),
+    };
+    if dir.is_dir() {
+        Ok(vec![dir])
+    } else {
+        Ok(vec![])
+    }
+}
+
+/// Finds the history file of a snapshot selected on the command line, as in `resolve`
+pub async fn resolve_snapshot(
+    history_dir: &Path,
+    args: &cli::SnapshotArgs,
+    default_root: &Path,
+) -> Result<PathBuf> {
+    resolve(history_dir, &args.namespace, default_root, &args.snapshot).await
+}
+
+/// Finds a history file from a command line argument.
+///
+/// The argument can be the path to a history file in the selected namespaces, `latest`,
+/// `latest~N` for the Nth snapshot before the latest one,
+/// or a unique prefix of a history file name in the selected namespaces, such as a date.
+///
+/// Paths outside the selected namespaces are rejected,
+/// since the caller may lock, index or remove the resolved file.
+pub async fn resolve(
+    history_dir: &Path,
+    namespace: &cli::NamespaceArgs,
+    default_root: &Path,
+    spec: &str,
+) -> Result<PathBuf> {
+    let dirs = select_namespaces(history_dir, namespace, default_root).await?;
+
+    let path = Path::new(spec);
+    if path.is_file() {
+        let path = path.canonicalize()?;
+        let in_namespace = path.parent().map_or(false, |parent| {
+            dirs.iter()
+                .any(|dir| dir.canonicalize().map_or(false, |dir| dir == parent))
+        });
+        if in_namespace && is_history_file(&path).await {
+            return Ok(path);
+        }
+        return Err(make_err(format!(
+            "{}: not a history file in the selected namespaces, \
+             select its namespace with --root or --all-namespaces",
+            spec
+        )));
+    }
+
+    let mut entries = vec![];
+    for dir in dirs {
+        for entry in list(&dir).await? {
+            entries.push((entry.timestamp, dir.join(&entry.file)));
+        }
+    }
+    entries.sort_by_key(|&(timestamp, _)| timestamp);
+
+    let back = if spec == "latest" {
+        Some(0)
+    } else if spec.starts_with("latest~") {
+        Some(spec["latest~".len()..].parse::<usize>().map_err(make_err)?)
+    } else {
+        None
+    };
+    if let Some(back) = back {
+        return match entries.iter().rev().nth(back) {
+            Some((_, path)) => Ok(path.clone()),
+            None => Err(make_err(format!(
+                "{}: there are only {} snapshots",
+                spec,
+                entries.len()
+            ))),
+        };
+    }
+
+    let mut matches = entries.into_iter().filter(|(_, path)| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with(spec))
+    });
+    match (matches.next(), matches.next()) {
+        (Some((_, path)), None) => Ok(path),
+        (Some(_), Some(_)) => Err(make_err(format!("{}: matches multiple snapshots", spec))),
+        (None, _) => Err(make_err(format!("{}: no such snapshot", spec))),
+    }
+}
+
+pub async fn write(report: &Report, dir: &PathBuf, format: Format) -> Result {
     log::info!("Writing history to {}", dir.display());
     fs::create_dir_all(&dir).await?;
+    let _lock = lock::acquire(dir).await?;
 
-    let date = LocalTz::now().format("%Y-%m-%d_%H-%M-%S");
-    let file_name = format!("{}.json", &date);
-    let file_path = dir.join(format!("{}.gz", &file_name));
-    let f = std::fs::File::create(&file_path)?; // TODO make this async
-    let f = GzBuilder::new()
-        .filename(file_name.as_str())
-        .comment(format!("Filesystem analysis on {}", &date))
-        .write(f, Compression::default());
-    let cw = CountWrite::from(f);
+    let date = LocalTz
+        .timestamp(report.meta.timestamp, 0)
+        .format(FILE_NAME_FORMAT)
+        .to_string();
+    let file_name = loop {
+        let file_name = format!(
+            "{}_{:06x}.{}",
+            &date,
+            rand::random::<u32>() & 0xff_ffff,
+            format.extension()
+        );
+        if !dir.join(&file_name).exists() {
+            break file_name;
+        }
+    };
+    let size = replace_file(report, format, dir, &file_name).await?;
+    log::info!(
+        "{} bytes written to {}",
+        size,
+        dir.join(&file_name).display()
+    );
 
-    let fmter = PrettyFormatter::with_indent(&[]);
-    let mut serer = Serializer::with_formatter(cw, fmter);
+    let entry = IndexEntry::new(file_name, report);
+    index::update(dir, |index| index.insert(entry)).await?;
 
-    tree.serialize(&mut serer).map_err(make_err)?;
+    Ok(())
+}
 
-    let cw = serer.into_inner();
-    let size = cw.count();
-    drop(cw);
+/// Atomically writes a report to `file_name` in `dir`,
+/// returning the number of bytes written before compression
+async fn replace_file(report: &Report, format: Format, dir: &Path, file_name: &str) -> Result<u64> {
+    let date = LocalTz
+        .timestamp(report.meta.timestamp, 0)
+        .format(FILE_NAME_FORMAT)
+        .to_string();
+    let temp_path = dir.join(format!("{}{}", TEMP_FILE_PREFIX, file_name));
 
-    log::info!("{} bytes written to {}", size, file_path.display());
+    let size = match write_file(report, format, &date, &temp_path).await {
+        Ok(size) => size,
+        Err(err) => {
+            drop(fs::remove_file(&temp_path).await);
+            return Err(err);
+        }
+    };
+    fs::rename(&temp_path, dir.join(file_name)).await?;
+    sync_dir(dir)?;
+    Ok(size)
+}
 
+/// Writes a report to `path` and flushes it to the disk.
+///
+/// The report is compressed in memory, so that only the file operations are awaited.
+async fn write_file(report: &Report, format: Format, date: &str, path: &Path) -> Result<u64> {
+    let (size, data) = match format.compression {
+        Compression::Gzip => {
+            let mut w = GzBuilder::new()
+                .filename(format!("{}.{}", date, format.encoding_extension()))
+                .comment(format!("Filesystem analysis on {}", date))
+                .write(vec![], flate2::Compression::default());
+            let size = format::encode(report, format.encoding, &mut w)?;
+            (size, w.finish()?)
+        }
+        Compression::Zstd => {
+            let mut w = zstd::Encoder::new(vec![], 0)?;
+            let size = format::encode(report, format.encoding, &mut w)?;
+            (size, w.finish()?)
+        }
+    };
+    let mut f = fs::File::create(path).await?;
+    f.write_all(&data).await?;
+    f.sync_all().await?;
+    Ok(size)
+}
+
+/// Flushes renames in a directory to the disk
+fn sync_dir(dir: &Path) -> Result {
+    #[cfg(unix)]
+    {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+    }
     Ok(())
 }
 
-pub async fn rotate(dir: &PathBuf, rotate_days: u32) -> Result {
-    let mut entries = vec![];
+/// Reads a history file in any format and schema version, upgrading it to the current schema
+pub async fn read(path: &Path) -> Result<Report> {
+    let (report, _) = read_versioned(path, None).await?;
+    Ok(report)
+}
 
-    let mut iter = fs::read_dir(dir.clone()).await?;
+/// Reads a history file, also returning the schema version it was written in.
+///
+/// Version 0 files can only be read with an `origin`, since they do not record the scan.
+async fn read_versioned(path: &Path, origin: Option<&Origin<'_>>) -> Result<(Report, u32)> {
+    let data = fs::read(path).await?;
+    let data = format::decompress(&data)?;
+    let versioned = format::decode(&data)?;
+    let version = versioned.version();
+
+    let timestamp = file_name_timestamp(path).unwrap_or(0);
+    let report = versioned.upgrade(timestamp, origin).ok_or_else(|| {
+        make_err(format!(
+            "{} does not record the scanned directory, \
+             migrate it with `fstree history migrate --root <scanned directory>`",
+            path.display()
+        ))
+    })?;
+    Ok((report, version))
+}
+
+/// Rewrites the history files in a directory that use an older schema version.
+///
+/// Version 0 files are attributed to `origin`, and skipped if it is `None`.
+async fn migrate(dir: &Path, origin: Option<&Origin<'_>>, dry_run: bool) -> Result {
+    let _lock = lock::acquire(dir).await?;
+
+    let mut iter = fs::read_dir(dir.to_path_buf()).await?;
     while let Some(entry) = iter.next().await {
         let entry = match entry {
             Ok(entry) => entry,
@@ -68,44 +355,496 @@ pub async fn rotate(dir: &PathBuf, rotate_days: u32) -> Result {
                 continue;
             }
         };
-        let meta = match entry.metadata().await {
-            Ok(meta) => meta,
+        let path = entry.path();
+        let (file_name, format) = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => match parse_file_name(name) {
+                Some((_, format)) => (name.to_string(), format),
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let (report, version) = match read_versioned(&path, origin).await {
+            Ok(ret) => ret,
             Err(err) => {
-                log::warn!("Failed to stat {}: {}", entry.path().display(), err);
+                log::error!("Failed to read {}: {}", path.display(), err);
                 continue;
             }
         };
-        let modified = match meta.modified() {
-            Ok(modified) => modified,
-            Err(_) => {
-                log::warn!(
-                    "Rotation aborted: Failed to retrieve last modification time of {}",
-                    entry.path().display()
+        if version == SCHEMA_VERSION {
+            continue;
+        }
+
+        if dry_run {
+            println!(
+                "Would migrate {} from version {} to {}",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            );
+            continue;
+        }
+        log::info!(
+            "Migrating {} from version {} to {}",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        );
+        replace_file(&report, format, dir, &file_name).await?;
+        let entry = IndexEntry::new(file_name, &report);
+        index::update(dir, |index| index.insert(entry)).await?;
+    }
+
+    Ok(())
+}
+
+/// Moves the history files stored directly in the history directory,
+/// which were written before namespaces were introduced, into the namespaces of their scans.
+///
+/// Version 0 files are attributed to `origin`, and left in place if it is `None`.
+async fn migrate_flat(history_dir: &Path, origin: Option<&Origin<'_>>, dry_run: bool) -> Result {
+    let files = flat_files(history_dir).await?;
+    if files.is_empty() {
+        return Ok(());
+    }
+    let _lock = lock::acquire(history_dir).await?;
+
+    for path in files {
+        let (file_name, format) = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => match parse_file_name(name) {
+                Some((_, format)) => (name.to_string(), format),
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let (report, _) = match read_versioned(&path, origin).await {
+            Ok(ret) => ret,
+            Err(err) => {
+                log::warn!("Not moving {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let dir = namespace_dir(history_dir, &report.meta);
+        if dir.join(&file_name).exists() {
+            log::warn!(
+                "Not moving {}: {} already exists",
+                path.display(),
+                dir.join(&file_name).display()
+            );
+            continue;
+        }
+
+        if dry_run {
+            println!("Would move {} to {}", path.display(), dir.display());
+            continue;
+        }
+        log::info!("Moving {} to {}", path.display(), dir.display());
+        fs::create_dir_all(&dir).await?;
+        {
+            let _lock = lock::acquire(&dir).await?;
+            replace_file(&report, format, &dir, &file_name).await?;
+            let entry = IndexEntry::new(file_name, &report);
+            index::update(&dir, |index| index.insert(entry)).await?;
+        }
+        fs::remove_file(&path).await?;
+    }
+
+    // an index of the flat files is only present if they were listed since the upgrade
+    if dry_run {
+        return Ok(());
+    }
+    if let Ok(Some(mut index)) = index::load(history_dir).await {
+        index
+            .entries
+            .retain(|entry| history_dir.join(&entry.file).exists());
+        index::save(history_dir, &index).await?;
+    }
+    Ok(())
+}
+
+/// Lists the history files stored directly in the history directory
+async fn flat_files(history_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    if !history_dir.is_dir() {
+        return Ok(files);
+    }
+
+    let mut iter = fs::read_dir(history_dir.to_path_buf()).await?;
+    while let Some(entry) = iter.next().await {
+        let path = entry?.path();
+        if path.is_file()
+            && path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| parse_file_name(name).is_some())
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Warns about history files written before namespaces were introduced,
+/// since they are no longer rotated until they are moved into their namespaces
+pub async fn warn_flat_files(history_dir: &Path) {
+    match flat_files(history_dir).await {
+        Ok(ref files) if files.is_empty() => {}
+        Ok(files) => log::warn!(
+            "{} history files in {} predate namespaces and are no longer rotated, \
+             move them with `fstree history migrate --root <scanned directory>`",
+            files.len(),
+            history_dir.display()
+        ),
+        Err(err) => log::error!("Failed scanning history directory: {}", err),
+    }
+}
+
+/// Parses the date and format from the name of a history file.
+///
+/// The date may be followed by an underscore and the hexadecimal suffix
+/// that keeps names unique within the same second.
+fn parse_file_name(name: &str) -> Option<(NaiveDateTime, Format)> {
+    let format = Format::from_file_name(name)?;
+    let stem = name.split('.').next()?;
+    let (date, suffix) = if stem.len() > FILE_NAME_DATE_LEN {
+        (stem.get(..FILE_NAME_DATE_LEN)?, &stem[FILE_NAME_DATE_LEN..])
+    } else {
+        (stem, "")
+    };
+    let unique_suffix =
+        suffix.starts_with('_') && suffix[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !suffix.is_empty() && !unique_suffix {
+        return None;
+    }
+    let date = NaiveDateTime::parse_from_str(date, FILE_NAME_FORMAT).ok()?;
+    Some((date, format))
+}
+
+/// Returns the time of a snapshot from the name of its history file
+fn file_name_timestamp(path: &Path) -> Option<i64> {
+    let (date, _) = parse_file_name(path.file_name()?.to_str()?)?;
+    Some(LocalTz.from_local_datetime(&date).single()?.timestamp())
+}
+
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| name.starts_with(TEMP_FILE_PREFIX))
+}
+
+/// Checks whether a file is a history file written by fstree,
+/// judging from both its name and its content
+async fn is_history_file(path: &Path) -> bool {
+    let format = match path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(parse_file_name)
+    {
+        Some((_, format)) => format,
+        None => return false,
+    };
+    format::is_history_content(path, format).await
+}
+
+/// Lists the snapshots in the history directory from the index, ordered by time.
+///
+/// The index is only written if it has to be rebuilt, which takes the lock of the directory,
+/// so this must not be called with the lock held.
+pub async fn list(dir: &Path) -> Result<Vec<IndexEntry>> {
+    if let Ok(Some(index)) = index::load(dir).await {
+        return Ok(index.entries);
+    }
+    let _lock = lock::acquire(dir).await?;
+    let index = index::load_or_rebuild(dir).await?;
+    Ok(index.entries)
+}
+
+pub async fn run_command(
+    cmd: &cli::HistoryCommand,
+    history_dir: &Path,
+    default_root: &Path,
+) -> Result {
+    match cmd {
+        cli::HistoryCommand::List { namespace } => {
+            for dir in select_namespaces(history_dir, namespace, default_root).await? {
+                let prefix = dir.strip_prefix(history_dir).unwrap_or(&dir);
+                for entry in list(&dir).await? {
+                    println!(
+                        "{}  {}  {}  {}  {}  {} files  {} errors",
+                        prefix.join(&entry.file).display(),
+                        LocalTz
+                            .timestamp(entry.timestamp, 0)
+                            .format("%Y-%m-%d %H:%M:%S"),
+                        entry.host.as_ref().map_or("-", String::as_str),
+                        entry.root.display(),
+                        cli::Byte(entry.size.real),
+                        entry.files,
+                        entry.errors,
+                    );
+                }
+            }
+        }
+        cli::HistoryCommand::Show { snapshot } => {
+            let path = resolve_snapshot(history_dir, snapshot, default_root).await?;
+            let (report, version) = read_versioned(&path, None).await?;
+            show(&path, &report, version);
+        }
+        cli::HistoryCommand::Rm {
+            snapshots,
+            namespace,
+            dry_run,
+        } => {
+            for snapshot in snapshots {
+                let path = resolve(history_dir, namespace, default_root, snapshot).await?;
+                if *dry_run {
+                    println!("Would remove {}", path.display());
+                } else {
+                    remove(&path).await?;
+                }
+            }
+        }
+        cli::HistoryCommand::Rotate {
+            namespace,
+            retention,
+            dry_run,
+        } => {
+            for dir in select_namespaces(history_dir, namespace, default_root).await? {
+                rotate(&dir, &retention.policy(), *dry_run).await?;
+            }
+        }
+        cli::HistoryCommand::Migrate { namespace, dry_run } => {
+            // version 0 files are only attributed to a directory the user explicitly names
+            let root = namespace
+                .root
+                .as_ref()
+                .map(|root| root.canonicalize().unwrap_or_else(|_| root.clone()));
+            let host = namespace.host.clone().or_else(local_host);
+            let origin = root.as_ref().map(|root| Origin {
+                root,
+                host: host.as_ref().map(String::as_str),
+            });
+            migrate_flat(history_dir, origin.as_ref(), *dry_run).await?;
+            for dir in select_namespaces(history_dir, namespace, default_root).await? {
+                if dir != *history_dir {
+                    migrate(&dir, origin.as_ref(), *dry_run).await?;
+                }
+            }
+        }
+        cli::HistoryCommand::Reindex { namespace } => {
+            for dir in select_namespaces(history_dir, namespace, default_root).await? {
+                let _lock = lock::acquire(&dir).await?;
+                let index = index::rebuild(&dir).await?;
+                index::save(&dir, &index).await?;
+                log::info!(
+                    "Indexed {} history files in {}",
+                    index.entries.len(),
+                    dir.display()
                 );
-                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the metadata and totals of a snapshot
+fn show(path: &Path, report: &Report, version: u32) {
+    let meta = &report.meta;
+    let total = report.tree.total();
+    println!("File:      {}", path.display());
+    println!("Schema:    version {}", version);
+    println!(
+        "Scanned:   {}",
+        LocalTz
+            .timestamp(meta.timestamp, 0)
+            .format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("Root:      {}", meta.root.display());
+    println!(
+        "Host:      {}",
+        meta.host.as_ref().map_or("-", String::as_str)
+    );
+    println!("Duration:  {:.1}s", meta.duration_ms as f64 / 1000.);
+    println!("Shake:     {}", cli::Byte(meta.shake));
+    println!(
+        "Size:      {} ({} content)",
+        cli::Byte(total.size().real),
+        cli::Byte(total.size().content)
+    );
+    println!("Files:     {}", total.files.count);
+    println!("Dirs:      {}", total.dirs.count);
+    println!("Errors:    {}", total.errors);
+
+    if let crawl::Node::Dir { children, .. } = &report.tree {
+        let mut children: Vec<_> = children.iter().collect();
+        children.sort_by_key(|child| Reverse(child.total().size().real));
+        println!();
+        for child in children.into_iter().take(10) {
+            println!(
+                "{:>12}  {}",
+                cli::Byte(child.total().size().real).to_string(),
+                child.name().map_or("(error)", |name| &**name)
+            );
+        }
+    }
+}
+
+/// Removes a history file and its index entry
+async fn remove(path: &Path) -> Result {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| make_err(format!("{}: not a history file", path.display())))?
+        .to_string();
+    let _lock = lock::acquire(dir).await?;
+
+    log::info!("Removing history file {}", path.display());
+    fs::remove_file(path).await?;
+    index::update(dir, |index| {
+        index.entries.retain(|entry| entry.file != file_name)
+    })
+    .await?;
+    Ok(())
+}
+
+/// Removes history files according to the retention policy.
+///
+/// Only files that look like history files or are listed in the index are considered,
+/// in case the history directory is shared with unrelated files.
+pub async fn rotate(dir: &PathBuf, policy: &Policy, dry_run: bool) -> Result {
+    let _lock = lock::acquire(dir).await?;
+
+    // the times of the snapshots, since the modification times change when files are rewritten
+    let indexed: HashMap<String, i64> = match index::load(dir).await {
+        Ok(Some(index)) => index
+            .entries
+            .into_iter()
+            .map(|entry| (entry.file, entry.timestamp))
+            .collect(),
+        Ok(None) => HashMap::new(),
+        Err(err) => {
+            log::warn!("Failed to load history index: {}", err);
+            HashMap::new()
+        }
+    };
+
+    let mut candidates = vec![];
+
+    let mut iter = fs::read_dir(dir.clone()).await?;
+    while let Some(entry) = iter.next().await {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::error!("Failed scanning history directory: {}", err);
+                continue;
             }
         };
-        let elapsed = match modified.elapsed() {
-            Ok(duration) => duration,
-            Err(_) => {
-                log::warn!(
-                    "History file {} is last modified in the future. Was the system time changed?",
+        if index::is_index_file(&entry.path())
+            || cache::is_cache_file(&entry.path())
+            || lock::is_lock_file(&entry.path())
+            || entry.path().is_dir()
+        {
+            continue;
+        }
+        if is_temp_file(&entry.path()) {
+            // no write can be in progress while the lock is held
+            if dry_run {
+                println!("Would remove {}", entry.path().display());
+            } else {
+                log::info!(
+                    "Removing temporary file {} left by an interrupted write",
                     entry.path().display()
                 );
+                if let Err(err) = fs::remove_file(entry.path()).await {
+                    log::error!("Failed to remove {}: {}", entry.path().display(), err);
+                }
+            }
+            continue;
+        }
+        let indexed_timestamp = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| indexed.get(name))
+            .cloned();
+        if indexed_timestamp.is_none() && !is_history_file(&entry.path()).await {
+            log::warn!(
+                "Skipping {} during rotation as it is not an fstree history file",
+                entry.path().display()
+            );
+            continue;
+        }
+        let meta = match entry.metadata().await {
+            Ok(meta) => meta,
+            Err(err) => {
+                log::warn!("Failed to stat {}: {}", entry.path().display(), err);
                 continue;
             }
         };
-        if elapsed.as_secs() > u64::from(rotate_days) * 86400 {
-            entries.push(entry.path());
+        let timestamp = match indexed_timestamp.or_else(|| file_name_timestamp(&entry.path())) {
+            Some(timestamp) => timestamp,
+            None => {
+                let modified = match meta.modified() {
+                    Ok(modified) => modified,
+                    Err(_) => {
+                        log::warn!(
+                            "Rotation aborted: Failed to retrieve last modification time of {}",
+                            entry.path().display()
+                        );
+                        return Ok(());
+                    }
+                };
+                if modified.elapsed().is_err() {
+                    log::warn!(
+                        "History file {} is last modified in the future. \
+                         Was the system time changed?",
+                        entry.path().display()
+                    );
+                    continue;
+                }
+                modified
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |duration| duration.as_secs() as i64)
+            }
+        };
+        candidates.push(Candidate {
+            path: entry.path(),
+            timestamp,
+            size: meta.len(),
+        });
+    }
+
+    let entries = policy.select(candidates, LocalTz::now().timestamp());
+
+    if dry_run {
+        for entry in &entries {
+            println!(
+                "Would remove {} ({})",
+                entry.path.display(),
+                cli::Byte(entry.size)
+            );
         }
+        return Ok(());
     }
 
+    let mut removed = vec![];
     for entry in entries {
-        log::info!("Removing old history file {}", entry.display());
-        if let Err(err) = fs::remove_file(&entry).await {
-            log::error!("Failed to remove {}: {}", entry.display(), err);
+        log::info!("Removing old history file {}", entry.path.display());
+        match fs::remove_file(&entry.path).await {
+            Ok(()) => removed.push(entry.path),
+            Err(err) => log::error!("Failed to remove {}: {}", entry.path.display(), err),
         }
     }
 
+    if !removed.is_empty() {
+        index::update(dir, |index| {
+            index
+                .entries
+                .retain(|entry| !removed.iter().any(|path| path.ends_with(&entry.file)))
+        })
+        .await?;
+    }
+
     Ok(())
 }
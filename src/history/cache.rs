@@ -0,0 +1,161 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::IndexEntry;
+use crate::crawl;
+
+const CACHE_FILE_NAME: &str = "path-cache.json";
+
+/// The maximum number of children stored for each cached path
+const MAX_CHILDREN: usize = 50;
+
+/// Caches the stats of individual paths in each snapshot,
+/// so that time series queries do not need to decompress every snapshot again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Cache {
+    /// Maps history file names to paths relative to the scanned directory.
+    /// A `None` value means the path does not exist or was hidden by leaf shaking.
+    files: HashMap<String, HashMap<String, Option<PathStats>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathStats {
+    pub size: crawl::Size,
+    /// The largest children of the path by real size
+    pub children: Vec<(String, crawl::Size)>,
+}
+
+impl PathStats {
+    fn new(node: &crawl::Node) -> Self {
+        let mut children: Vec<_> = match node {
+            crawl::Node::Dir { children, .. } => children
+                .iter()
+                .filter_map(|child| {
+                    let name = child.name()?;
+                    Some((name.to_string(), child.total().size()))
+                })
+                .collect(),
+            _ => vec![],
+        };
+        children.sort_by_key(|(_, size)| std::cmp::Reverse(size.real));
+        children.truncate(MAX_CHILDREN);
+        Self {
+            size: node.total().size(),
+            children,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SeriesPoint {
+    pub entry: IndexEntry,
+    pub stats: Option<PathStats>,
+}
+
+pub fn is_cache_file(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name == CACHE_FILE_NAME || name == format!("{}.tmp", CACHE_FILE_NAME),
+        None => false,
+    }
+}
+
+/// Returns the stats of an absolute path in every snapshot of a history directory that covers it,
+/// starting from the Unix timestamp `since`
+pub async fn path_series(dir: &Path, path: &Path, since: i64) -> Result<Vec<SeriesPoint>> {
+    let entries = super::list(dir).await?;
+    let mut cache = load(dir).await;
+    cache
+        .files
+        .retain(|file, _| entries.iter().any(|entry| &entry.file == file));
+
+    let mut dirty = false;
+    let mut series = vec![];
+    for entry in entries {
+        if entry.timestamp < since {
+            continue;
+        }
+        let rel = match path.strip_prefix(&entry.root) {
+            Ok(rel) => rel,
+            Err(_) => continue,
+        };
+        let key = rel.to_string_lossy().into_owned();
+
+        let paths = cache.files.entry(entry.file.clone()).or_default();
+        let stats = match paths.get(&key) {
+            Some(stats) => stats.clone(),
+            None => {
+                let report = match super::read(&dir.join(&entry.file)).await {
+                    Ok(report) => report,
+                    Err(err) => {
+                        log::warn!("Failed to read {}: {}", entry.file, err);
+                        continue;
+                    }
+                };
+                let stats = report.tree.find(rel).map(PathStats::new);
+                paths.insert(key, stats.clone());
+                dirty = true;
+                stats
+            }
+        };
+        series.push(SeriesPoint { entry, stats });
+    }
+
+    if dirty {
+        if let Err(err) = save(dir, &cache).await {
+            log::warn!("Failed to save path cache: {}", err);
+        }
+    }
+
+    Ok(series)
+}
+
+async fn load(dir: &Path) -> Cache {
+    let json = match fs::read(dir.join(CACHE_FILE_NAME)).await {
+        Ok(json) => json,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("Failed to read path cache: {}", err);
+            }
+            return Cache::default();
+        }
+    };
+    match serde_json::from_slice(&json) {
+        Ok(cache) => cache,
+        Err(err) => {
+            log::warn!("Discarding corrupted path cache: {}", err);
+            Cache::default()
+        }
+    }
+}
+
+/// Atomically replaces the path cache, taking the lock since the temporary file name is fixed
+async fn save(dir: &Path, cache: &Cache) -> Result {
+    let json = serde_json::to_vec(cache).map_err(make_err)?;
+    let _lock = super::lock::acquire(dir).await?;
+    let tmp = dir.join(format!("{}.tmp", CACHE_FILE_NAME));
+    fs::write(tmp.clone(), json).await?;
+    fs::rename(&tmp, dir.join(CACHE_FILE_NAME)).await?;
+    Ok(())
+}
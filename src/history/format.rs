@@ -0,0 +1,629 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use count_write::CountWrite;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use serde_json::ser::{PrettyFormatter, Serializer};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use super::legacy::Versioned;
+use super::{Report, ReportMeta, SCHEMA_VERSION};
+use crate::crawl::{self, FileTypeExt, NodeStats, Size, StringRef, TypedStats};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// The magic bytes of the binary encoding, followed by the schema version as a little-endian u32
+const BINARY_MAGIC: &[u8] = b"FSTV";
+/// The number of decompressed bytes inspected to recognize a history file
+const PROBE_LEN: u64 = 256;
+/// The number of compressed bytes read to recognize a history file,
+/// enough for a whole zstd block, which is only decompressed once complete
+const PROBE_READ_LEN: u64 = 132 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Pretty-printed JSON with one field per line
+    Json,
+    /// bincode with a string table in front of the tree
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// The on-disk format of a history file, written as `<encoding>.<compression>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub compression: Compression,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Json,
+            compression: Compression::Gzip,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        let mut parts = str.splitn(2, '.');
+        let encoding = match parts.next() {
+            Some("json") => Encoding::Json,
+            Some("bin") => Encoding::Binary,
+            _ => return Err(format!("Unknown history encoding in {:?}", str)),
+        };
+        let compression = match parts.next() {
+            None | Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some(other) => return Err(format!("Unknown history compression {:?}", other)),
+        };
+        Ok(Self {
+            encoding,
+            compression,
+        })
+    }
+}
+
+impl Format {
+    /// The file extension of the uncompressed content, e.g. `json`
+    pub fn encoding_extension(self) -> &'static str {
+        match self.encoding {
+            Encoding::Json => "json",
+            Encoding::Binary => "bin",
+        }
+    }
+
+    /// The full file extension, e.g. `json.gz`
+    pub fn extension(self) -> String {
+        let compression = match self.compression {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        };
+        format!("{}.{}", self.encoding_extension(), compression)
+    }
+
+    /// Infers the format of a history file from its name
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let mut parts = name.splitn(2, '.');
+        parts.next()?;
+        let ext = parts.next()?;
+        let format = ext.parse::<Self>().ok()?;
+        if format.extension() == ext {
+            Some(format)
+        } else {
+            None
+        }
+    }
+}
+
+/// Checks whether a file was written by fstree in a format,
+/// by decompressing its leading bytes and looking for the magic bytes of the binary encoding
+/// or the top-level keys of the JSON encoding of any schema version
+pub async fn is_history_content(path: &Path, format: Format) -> bool {
+    let mut compressed = vec![];
+    match fs::File::open(path).await {
+        Ok(f) => {
+            if f.take(PROBE_READ_LEN)
+                .read_to_end(&mut compressed)
+                .await
+                .is_err()
+            {
+                return false;
+            }
+        }
+        Err(_) => return false,
+    }
+
+    // the compressed bytes usually end mid-stream,
+    // so whatever was decompressed before the error is inspected
+    let mut prefix = vec![];
+    match format.compression {
+        Compression::Gzip => {
+            let _ = GzDecoder::new(&compressed[..])
+                .take(PROBE_LEN)
+                .read_to_end(&mut prefix);
+        }
+        Compression::Zstd => match zstd::stream::read::Decoder::new(&compressed[..]) {
+            Ok(decoder) => {
+                let _ = decoder.take(PROBE_LEN).read_to_end(&mut prefix);
+            }
+            Err(_) => return false,
+        },
+    }
+
+    match format.encoding {
+        Encoding::Binary => prefix.starts_with(BINARY_MAGIC),
+        Encoding::Json => {
+            let text = String::from_utf8_lossy(&prefix);
+            let text = text.trim_start();
+            // schema version 0 has no metadata, and its root is a directory node
+            text.starts_with('{')
+                && ["\"version\"", "\"meta\"", "\"Dir\""]
+                    .iter()
+                    .any(|key| text.contains(key))
+        }
+    }
+}
+
+/// Serializes a report into `w` without compression, returning the number of bytes written
+pub fn encode<W: Write>(report: &Report, encoding: Encoding, w: W) -> Result<u64> {
+    let mut cw = CountWrite::from(w);
+    match encoding {
+        Encoding::Json => {
+            let fmter = PrettyFormatter::with_indent(&[]);
+            let mut serer = Serializer::with_formatter(&mut cw, fmter);
+            let versioned = VersionedReport {
+                version: SCHEMA_VERSION,
+                meta: &report.meta,
+                tree: &report.tree,
+            };
+            versioned.serialize(&mut serer).map_err(make_err)?;
+        }
+        Encoding::Binary => {
+            cw.write_all(BINARY_MAGIC)?;
+            cw.write_all(&SCHEMA_VERSION.to_le_bytes())?;
+            let packed = Packer::default().pack_report(report);
+            bincode::serialize_into(&mut cw, &packed).map_err(make_err)?;
+        }
+    }
+    Ok(cw.count())
+}
+
+/// Decompresses a history file, detecting the compression from its magic bytes
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.starts_with(GZIP_MAGIC) {
+        let mut buf = vec![];
+        GzDecoder::new(data).read_to_end(&mut buf)?;
+        Ok(buf)
+    } else if data.starts_with(ZSTD_MAGIC) {
+        Ok(zstd::decode_all(data)?)
+    } else {
+        Err(make_err("Unknown history file compression"))
+    }
+}
+
+/// Decodes decompressed history data in any encoding and schema version
+pub fn decode(data: &[u8]) -> Result<Versioned> {
+    if data.starts_with(BINARY_MAGIC) {
+        let data = &data[BINARY_MAGIC.len()..];
+        if data.len() < 4 {
+            return Err(make_err("Truncated history file"));
+        }
+        let mut version = [0; 4];
+        version.copy_from_slice(&data[..4]);
+        let version = u32::from_le_bytes(version);
+        check_version(version)?;
+        let packed: PackedReport = bincode::deserialize(&data[4..]).map_err(make_err)?;
+        return Ok(Versioned::Current(packed.unpack()?));
+    }
+
+    // only the version is read here, the tree is skipped without being stored
+    let probe: VersionProbe = serde_json::from_slice(data).map_err(make_err)?;
+    match probe.version {
+        Some(version) => {
+            check_version(version)?;
+            let report = serde_json::from_slice(data).map_err(make_err)?;
+            Ok(Versioned::Current(report))
+        }
+        None => {
+            let tree = serde_json::from_slice(data).map_err(make_err)?;
+            Ok(Versioned::V0(tree))
+        }
+    }
+}
+
+/// Rejects schema versions not written by this version of fstree.
+/// Older schemas are recognized by their layout instead of a version field.
+fn check_version(version: u32) -> Result {
+    if version > SCHEMA_VERSION {
+        Err(make_err(format!(
+            "Unsupported history schema version {}, this version of fstree supports up to {}",
+            version, SCHEMA_VERSION
+        )))
+    } else if version < SCHEMA_VERSION {
+        Err(make_err(format!(
+            "Unknown history schema version {}",
+            version
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// The fields of a JSON history file needed to pick the schema to decode it with
+#[derive(Deserialize)]
+struct VersionProbe {
+    /// Absent in schema version 0
+    version: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct VersionedReport<'t> {
+    version: u32,
+    meta: &'t ReportMeta,
+    tree: &'t crawl::Node,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PackedReport {
+    meta: ReportMeta,
+    strings: Vec<String>,
+    tree: PackedNode,
+}
+
+type StringId = u32;
+
+#[derive(Serialize, Deserialize)]
+enum PackedNode {
+    File {
+        name: StringId,
+        extension: StringId,
+        size: Size,
+    },
+    Dir {
+        name: StringId,
+        children: Vec<PackedNode>,
+        total: TypedStats,
+        by_extension: Vec<(PackedFileTypeExt, TypedStats)>,
+    },
+    Link {
+        name: StringId,
+        size: Size,
+    },
+    Error {
+        name: Option<StringId>,
+        error: String,
+    },
+    BlockDevice {
+        name: StringId,
+        size: Size,
+    },
+    CharDevice {
+        name: StringId,
+        size: Size,
+    },
+    NamedPipe {
+        name: StringId,
+        size: Size,
+    },
+    UnixSocket {
+        name: StringId,
+        size: Size,
+    },
+    Other {
+        name: StringId,
+        extension: StringId,
+        size: Size,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum PackedFileTypeExt {
+    File(StringId),
+    Dir,
+    Link,
+    BlockDevice,
+    CharDevice,
+    NamedPipe,
+    UnixSocket,
+    Error,
+    Other(StringId),
+}
+
+/// Builds the string table of a packed report.
+///
+/// Each distinct string is stored once and referenced by its index in the table.
+/// The `StringPool` of a crawl is not reused here: it is a set that shares allocations,
+/// while the table needs an index for each string in the order it is first seen.
+/// Reports read back from history or imported were not interned by any pool either,
+/// so strings are deduplicated by value rather than by pointer.
+#[derive(Default)]
+struct Packer {
+    ids: HashMap<StringRef, StringId>,
+    strings: Vec<String>,
+}
+
+impl Packer {
+    fn pack_report(mut self, report: &Report) -> PackedReport {
+        let tree = self.pack_node(&report.tree);
+        PackedReport {
+            meta: report.meta.clone(),
+            strings: self.strings,
+            tree,
+        }
+    }
+
+    fn string(&mut self, str: &StringRef) -> StringId {
+        if let Some(&id) = self.ids.get(str) {
+            return id;
+        }
+        let id = self.strings.len() as StringId;
+        self.strings.push(str.to_string());
+        self.ids.insert(StringRef::clone(str), id);
+        id
+    }
+
+    fn pack_node(&mut self, node: &crawl::Node) -> PackedNode {
+        use crawl::Node;
+
+        match node {
+            Node::File {
+                name,
+                extension,
+                size,
+            } => PackedNode::File {
+                name: self.string(name),
+                extension: self.string(extension),
+                size: *size,
+            },
+            Node::Dir {
+                name,
+                children,
+                stats,
+            } => PackedNode::Dir {
+                name: self.string(name),
+                children: children.iter().map(|child| self.pack_node(child)).collect(),
+                total: stats.total,
+                by_extension: stats
+                    .by_extension
+                    .iter()
+                    .map(|(fte, typed)| (self.pack_fte(fte), *typed))
+                    .collect(),
+            },
+            Node::Link { name, size } => PackedNode::Link {
+                name: self.string(name),
+                size: *size,
+            },
+            Node::Error { name, error } => PackedNode::Error {
+                name: name.as_ref().map(|name| self.string(name)),
+                error: error.to_string(),
+            },
+            Node::BlockDevice { name, size } => PackedNode::BlockDevice {
+                name: self.string(name),
+                size: *size,
+            },
+            Node::CharDevice { name, size } => PackedNode::CharDevice {
+                name: self.string(name),
+                size: *size,
+            },
+            Node::NamedPipe { name, size } => PackedNode::NamedPipe {
+                name: self.string(name),
+                size: *size,
+            },
+            Node::UnixSocket { name, size } => PackedNode::UnixSocket {
+                name: self.string(name),
+                size: *size,
+            },
+            Node::Other {
+                name,
+                extension,
+                size,
+            } => PackedNode::Other {
+                name: self.string(name),
+                extension: self.string(extension),
+                size: *size,
+            },
+        }
+    }
+
+    fn pack_fte(&mut self, fte: &FileTypeExt) -> PackedFileTypeExt {
+        match fte {
+            FileTypeExt::File(ext) => PackedFileTypeExt::File(self.string(ext)),
+            FileTypeExt::Dir => PackedFileTypeExt::Dir,
+            FileTypeExt::Link => PackedFileTypeExt::Link,
+            FileTypeExt::BlockDevice => PackedFileTypeExt::BlockDevice,
+            FileTypeExt::CharDevice => PackedFileTypeExt::CharDevice,
+            FileTypeExt::NamedPipe => PackedFileTypeExt::NamedPipe,
+            FileTypeExt::UnixSocket => PackedFileTypeExt::UnixSocket,
+            FileTypeExt::Error => PackedFileTypeExt::Error,
+            FileTypeExt::Other(ext) => PackedFileTypeExt::Other(self.string(ext)),
+        }
+    }
+}
+
+impl PackedReport {
+    fn unpack(self) -> Result<Report> {
+        let strings: Vec<StringRef> = self.strings.into_iter().map(StringRef::from).collect();
+        Ok(Report {
+            meta: self.meta,
+            tree: unpack_node(&strings, self.tree)?,
+        })
+    }
+}
+
+fn unpack_node(strings: &[StringRef], node: PackedNode) -> Result<crawl::Node> {
+    use crawl::Node;
+
+    let string = |id: StringId| lookup(strings, id);
+    Ok(match node {
+        PackedNode::File {
+            name,
+            extension,
+            size,
+        } => Node::File {
+            name: string(name)?,
+            extension: string(extension)?,
+            size,
+        },
+        PackedNode::Dir {
+            name,
+            children,
+            total,
+            by_extension,
+        } => Node::Dir {
+            name: string(name)?,
+            children: children
+                .into_iter()
+                .map(|child| unpack_node(strings, child))
+                .collect::<Result<_>>()?,
+            stats: NodeStats {
+                total,
+                by_extension: by_extension
+                    .into_iter()
+                    .map(|(fte, typed)| Ok((unpack_fte(strings, fte)?, typed)))
+                    .collect::<Result<_>>()?,
+            },
+        },
+        PackedNode::Link { name, size } => Node::Link {
+            name: string(name)?,
+            size,
+        },
+        PackedNode::Error { name, error } => Node::Error {
+            name: name.map(string).transpose()?,
+            error: crawl::StaticError::from(error),
+        },
+        PackedNode::BlockDevice { name, size } => Node::BlockDevice {
+            name: string(name)?,
+            size,
+        },
+        PackedNode::CharDevice { name, size } => Node::CharDevice {
+            name: string(name)?,
+            size,
+        },
+        PackedNode::NamedPipe { name, size } => Node::NamedPipe {
+            name: string(name)?,
+            size,
+        },
+        PackedNode::UnixSocket { name, size } => Node::UnixSocket {
+            name: string(name)?,
+            size,
+        },
+        PackedNode::Other {
+            name,
+            extension,
+            size,
+        } => Node::Other {
+            name: string(name)?,
+            extension: string(extension)?,
+            size,
+        },
+    })
+}
+
+fn unpack_fte(strings: &[StringRef], fte: PackedFileTypeExt) -> Result<FileTypeExt> {
+    let string = |id: StringId| lookup(strings, id);
+    Ok(match fte {
+        PackedFileTypeExt::File(ext) => FileTypeExt::File(string(ext)?),
+        PackedFileTypeExt::Dir => FileTypeExt::Dir,
+        PackedFileTypeExt::Link => FileTypeExt::Link,
+        PackedFileTypeExt::BlockDevice => FileTypeExt::BlockDevice,
+        PackedFileTypeExt::CharDevice => FileTypeExt::CharDevice,
+        PackedFileTypeExt::NamedPipe => FileTypeExt::NamedPipe,
+        PackedFileTypeExt::UnixSocket => FileTypeExt::UnixSocket,
+        PackedFileTypeExt::Error => FileTypeExt::Error,
+        PackedFileTypeExt::Other(ext) => FileTypeExt::Other(string(ext)?),
+    })
+}
+
+/// Looks up a string in the string table of a packed report
+fn lookup(strings: &[StringRef], id: StringId) -> Result<StringRef> {
+    strings
+        .get(id as usize)
+        .cloned()
+        .ok_or_else(|| make_err("Corrupt history file: string id out of range"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn report() -> Report {
+        let size = Size {
+            real: 4096,
+            content: 100,
+        };
+        let file = crawl::Node::File {
+            name: "a.txt".into(),
+            extension: "txt".into(),
+            size,
+        };
+        Report {
+            meta: ReportMeta {
+                timestamp: 1_600_000_000,
+                root: PathBuf::from("/data"),
+                host: Some("box".into()),
+                shake: 0,
+                duration_ms: 0,
+            },
+            tree: crawl::dir_node(0, "data".into(), size, vec![file]),
+        }
+    }
+
+    fn decode_current(data: &[u8]) -> Report {
+        match decode(data).unwrap() {
+            Versioned::Current(report) => report,
+            Versioned::V0(_) => panic!("decoded as version 0"),
+        }
+    }
+
+    #[test]
+    fn round_trip_in_both_encodings() {
+        let report = report();
+        for &encoding in &[Encoding::Json, Encoding::Binary] {
+            let mut data = vec![];
+            encode(&report, encoding, &mut data).unwrap();
+            let decoded = decode_current(&data);
+            assert_eq!(decoded.meta.root, report.meta.root);
+            assert_eq!(decoded.meta.host, report.meta.host);
+            assert_eq!(decoded.meta.timestamp, report.meta.timestamp);
+            let total = decoded.tree.total();
+            assert_eq!(total.files.count, 1);
+            assert_eq!(total.size().content, report.tree.total().size().content);
+        }
+    }
+
+    #[test]
+    fn decodes_version_0() {
+        let stats = r#"{"files": {"count": 0, "size": {"real": 0, "content": 0}},
+            "dirs": {"count": 1, "size": {"real": 4096, "content": 4096}},
+            "others": {"count": 0, "size": {"real": 0, "content": 0}}, "errors": 0}"#;
+        let data = format!(
+            r#"{{"Dir": {{"name": "data", "children": [], "stats": {{"total": {0}, "by_extension": [["Dir", {0}]]}}}}}}"#,
+            stats
+        );
+        match decode(data.as_bytes()).unwrap() {
+            Versioned::V0(tree) => assert_eq!(tree.name().map(|name| &**name), Some("data")),
+            Versioned::Current(_) => panic!("decoded as the current version"),
+        }
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let data = format!(
+            r#"{{"version": {}, "meta": {{}}, "tree": {{}}}}"#,
+            SCHEMA_VERSION + 1
+        );
+        assert!(decode(data.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures_util::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::{Format, Report};
+use crate::crawl;
+
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// The summary of every snapshot in a history directory,
+/// so that snapshots can be listed without decompressing them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Index {
+    /// Adds an entry, replacing any existing entry for the same file
+    pub fn insert(&mut self, entry: IndexEntry) {
+        self.entries.retain(|other| other.file != entry.file);
+        let pos = self
+            .entries
+            .iter()
+            .position(|other| other.timestamp > entry.timestamp)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(pos, entry);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The file name of the snapshot, relative to the history directory
+    pub file: String,
+    pub timestamp: i64,
+    pub root: PathBuf,
+    pub host: Option<String>,
+    pub size: crawl::Size,
+    pub files: usize,
+    pub errors: usize,
+}
+
+impl IndexEntry {
+    pub fn new(file: String, report: &Report) -> Self {
+        let total = report.tree.stats().total;
+        Self {
+            file,
+            timestamp: report.meta.timestamp,
+            root: report.meta.root.clone(),
+            host: report.meta.host.clone(),
+            size: total.size(),
+            files: total.files.count,
+            errors: total.errors,
+        }
+    }
+}
+
+pub fn is_index_file(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name == INDEX_FILE_NAME || name == format!("{}.tmp", INDEX_FILE_NAME),
+        None => false,
+    }
+}
+
+/// Loads the index of a history directory, returning `None` if it does not exist
+pub async fn load(dir: &Path) -> Result<Option<Index>> {
+    let json = match fs::read(dir.join(INDEX_FILE_NAME)).await {
+        Ok(json) => json,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let index = serde_json::from_slice(&json).map_err(make_err)?;
+    Ok(Some(index))
+}
+
+/// Loads the index of a history directory, rebuilding it if it is missing or corrupted.
+///
+/// The lock of the directory must be held, since the rebuilt index is saved.
+pub async fn load_or_rebuild(dir: &Path) -> Result<Index> {
+    match load(dir).await {
+        Ok(Some(index)) => return Ok(index),
+        Ok(None) => log::info!("History index is missing, rebuilding"),
+        Err(err) => log::warn!("Failed to load history index, rebuilding: {}", err),
+    }
+    let index = rebuild(dir).await?;
+    save(dir, &index).await?;
+    Ok(index)
+}
+
+/// Atomically replaces the index file of a history directory.
+///
+/// The lock of the directory must be held, since the temporary file name is fixed.
+pub async fn save(dir: &Path, index: &Index) -> Result {
+    let json = serde_json::to_vec(index).map_err(make_err)?;
+    let tmp = dir.join(format!("{}.tmp", INDEX_FILE_NAME));
+    fs::write(tmp.clone(), json).await?;
+    fs::rename(&tmp, dir.join(INDEX_FILE_NAME)).await?;
+    Ok(())
+}
+
+/// Modifies the index of a history directory, which must be locked
+pub async fn update<F>(dir: &Path, f: F) -> Result
+where
+    F: FnOnce(&mut Index),
+{
+    let mut index = load_or_rebuild(dir).await?;
+    f(&mut index);
+    save(dir, &index).await
+}
+
+/// Rebuilds the index by reading every snapshot in the history directory
+pub async fn rebuild(dir: &Path) -> Result<Index> {
+    let mut index = Index::default();
+
+    let mut iter = fs::read_dir(dir.to_path_buf()).await?;
+    while let Some(entry) = iter.next().await {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                log::error!("Failed scanning history directory: {}", err);
+                continue;
+            }
+        };
+        let path = entry.path();
+        let file = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if Format::from_file_name(name).is_some() => name.to_string(),
+            _ => continue,
+        };
+        match super::read(&path).await {
+            Ok(report) => index.insert(IndexEntry::new(file, &report)),
+            Err(err) => log::warn!(
+                "Skipping unreadable history file {}: {}",
+                path.display(),
+                err
+            ),
+        }
+    }
+
+    Ok(index)
+}
@@ -0,0 +1,181 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Frozen definitions of older history schemas and functions to upgrade them.
+//!
+//! Each `upgrade_vN` function converts version N to version N + 1,
+//! so that only the last one needs to change when the current schema changes.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::Path;
+
+use super::{Report, ReportMeta};
+use crate::crawl;
+
+pub mod v0;
+
+/// A decoded history file in any schema version
+pub enum Versioned {
+    V0(v0::Node),
+    Current(Report),
+}
+
+impl Versioned {
+    pub fn version(&self) -> u32 {
+        match self {
+            Versioned::V0(_) => 0,
+            Versioned::Current(_) => super::SCHEMA_VERSION,
+        }
+    }
+
+    /// Upgrades to the current schema.
+    ///
+    /// `timestamp` and `origin` are used for version 0 files, which do not record the scan.
+    /// Returns `None` for version 0 files without an `origin`,
+    /// since the scanned directory cannot be recovered from the file.
+    pub fn upgrade(self, timestamp: i64, origin: Option<&Origin<'_>>) -> Option<Report> {
+        match self {
+            Versioned::V0(tree) => origin.map(|origin| upgrade_v0(tree, timestamp, origin)),
+            Versioned::Current(report) => Some(report),
+        }
+    }
+}
+
+/// The scan that wrote a version 0 history file, as given by the user
+pub struct Origin<'t> {
+    pub root: &'t Path,
+    pub host: Option<&'t str>,
+}
+
+/// Version 1 adds the report metadata and the explicit version field.
+pub fn upgrade_v0(tree: v0::Node, timestamp: i64, origin: &Origin<'_>) -> Report {
+    let meta = ReportMeta {
+        timestamp,
+        root: origin.root.to_path_buf(),
+        host: origin.host.map(String::from),
+        shake: 0,
+        duration_ms: 0,
+    };
+    Report {
+        meta,
+        tree: upgrade_v0_node(tree),
+    }
+}
+
+fn upgrade_v0_node(node: v0::Node) -> crawl::Node {
+    use crawl::Node;
+
+    match node {
+        v0::Node::File {
+            name,
+            extension,
+            size,
+        } => Node::File {
+            name,
+            extension,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::Dir {
+            name,
+            children,
+            stats,
+        } => Node::Dir {
+            name,
+            children: children.into_iter().map(upgrade_v0_node).collect(),
+            stats: crawl::NodeStats {
+                total: upgrade_v0_typed(stats.total),
+                by_extension: stats
+                    .by_extension
+                    .into_iter()
+                    .map(|(fte, typed)| (upgrade_v0_fte(fte), upgrade_v0_typed(typed)))
+                    .collect(),
+            },
+        },
+        v0::Node::Link { name, size } => Node::Link {
+            name,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::Error { name, error } => Node::Error {
+            name,
+            error: crawl::StaticError::from(error.0),
+        },
+        v0::Node::BlockDevice { name, size } => Node::BlockDevice {
+            name,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::CharDevice { name, size } => Node::CharDevice {
+            name,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::NamedPipe { name, size } => Node::NamedPipe {
+            name,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::UnixSocket { name, size } => Node::UnixSocket {
+            name,
+            size: upgrade_v0_size(size),
+        },
+        v0::Node::Other {
+            name,
+            extension,
+            size,
+        } => Node::Other {
+            name,
+            extension,
+            size: upgrade_v0_size(size),
+        },
+    }
+}
+
+fn upgrade_v0_fte(fte: v0::FileTypeExt) -> crawl::FileTypeExt {
+    use crawl::FileTypeExt;
+
+    match fte {
+        v0::FileTypeExt::File(ext) => FileTypeExt::File(ext),
+        v0::FileTypeExt::Dir => FileTypeExt::Dir,
+        v0::FileTypeExt::Link => FileTypeExt::Link,
+        v0::FileTypeExt::BlockDevice => FileTypeExt::BlockDevice,
+        v0::FileTypeExt::CharDevice => FileTypeExt::CharDevice,
+        v0::FileTypeExt::NamedPipe => FileTypeExt::NamedPipe,
+        v0::FileTypeExt::UnixSocket => FileTypeExt::UnixSocket,
+        v0::FileTypeExt::Error => FileTypeExt::Error,
+        v0::FileTypeExt::Other(ext) => FileTypeExt::Other(ext),
+    }
+}
+
+fn upgrade_v0_typed(typed: v0::TypedStats) -> crawl::TypedStats {
+    crawl::TypedStats {
+        files: upgrade_v0_agg(typed.files),
+        dirs: upgrade_v0_agg(typed.dirs),
+        others: upgrade_v0_agg(typed.others),
+        errors: typed.errors,
+    }
+}
+
+fn upgrade_v0_agg(agg: v0::AggStats) -> crawl::AggStats {
+    crawl::AggStats {
+        count: agg.count,
+        size: upgrade_v0_size(agg.size),
+    }
+}
+
+fn upgrade_v0_size(size: v0::Size) -> crawl::Size {
+    crawl::Size {
+        real: size.real,
+        content: size.content,
+    }
+}
@@ -0,0 +1,129 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Schema version 0: a bare `crawl::Node` tree without any metadata.
+//!
+//! These definitions are frozen copies and must not be changed
+//! when `crawl::Node` changes.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+pub type StringRef = Arc<str>;
+
+#[derive(Debug, Deserialize)]
+pub enum Node {
+    File {
+        name: StringRef,
+        extension: StringRef,
+        size: Size,
+    },
+    Dir {
+        name: StringRef,
+        children: Vec<Node>,
+        stats: NodeStats,
+    },
+    Link {
+        name: StringRef,
+        size: Size,
+    },
+    Error {
+        name: Option<StringRef>,
+        error: StaticError,
+    },
+    BlockDevice {
+        name: StringRef,
+        size: Size,
+    },
+    CharDevice {
+        name: StringRef,
+        size: Size,
+    },
+    NamedPipe {
+        name: StringRef,
+        size: Size,
+    },
+    UnixSocket {
+        name: StringRef,
+        size: Size,
+    },
+    Other {
+        name: StringRef,
+        extension: StringRef,
+        size: Size,
+    },
+}
+
+impl Node {
+    pub fn name(&self) -> Option<&StringRef> {
+        match self {
+            Node::File { name, .. }
+            | Node::Dir { name, .. }
+            | Node::Link { name, .. }
+            | Node::BlockDevice { name, .. }
+            | Node::CharDevice { name, .. }
+            | Node::NamedPipe { name, .. }
+            | Node::UnixSocket { name, .. }
+            | Node::Other { name, .. } => Some(name),
+            Node::Error { name, .. } => name.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StaticError(pub String);
+
+#[derive(Debug, Deserialize)]
+pub enum FileTypeExt {
+    File(StringRef),
+    Dir,
+    Link,
+    BlockDevice,
+    CharDevice,
+    NamedPipe,
+    UnixSocket,
+    Error,
+    Other(StringRef),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NodeStats {
+    pub total: TypedStats,
+    pub by_extension: Vec<(FileTypeExt, TypedStats)>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TypedStats {
+    pub files: AggStats,
+    pub dirs: AggStats,
+    pub others: AggStats,
+    pub errors: usize,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct AggStats {
+    pub count: usize,
+    pub size: Size,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Size {
+    pub real: u64,
+    pub content: u64,
+}
@@ -0,0 +1,74 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::time::Duration;
+
+use fs2::FileExt;
+use tokio::timer;
+
+const LOCK_FILE_NAME: &str = ".lock";
+/// The delay before the first retry to lock a history directory held by another process
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// The longest delay between retries to lock a history directory
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// An advisory lock on a history directory, released when dropped.
+///
+/// The lock is held by the open file description,
+/// so it must not be acquired twice in the same process.
+pub struct Lock(File);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        drop(self.0.unlock());
+    }
+}
+
+/// Locks a history directory, waiting for other fstree processes to release it.
+///
+/// The lock is polled with exponential backoff instead of a blocking call,
+/// so that waiting does not hold up the runtime.
+pub async fn acquire(dir: &Path) -> Result<Lock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(dir.join(LOCK_FILE_NAME))?;
+    let mut delay = INITIAL_RETRY_DELAY;
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(Lock(file)),
+            Err(err) if err.kind() == fs2::lock_contended_error().kind() => {}
+            Err(err) => return Err(err.into()),
+        }
+        if delay == INITIAL_RETRY_DELAY {
+            log::info!(
+                "Waiting for another fstree process to release {}",
+                dir.display()
+            );
+        }
+        timer::delay_for(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
+
+pub fn is_lock_file(path: &Path) -> bool {
+    path.file_name()
+        .map_or(false, |name| name == LOCK_FILE_NAME)
+}
@@ -0,0 +1,115 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::{Path, PathBuf};
+
+use futures_util::stream::StreamExt;
+use tokio::fs;
+
+/// The maximum length of the readable path part of a namespace name
+const MAX_PATH_LEN: usize = 64;
+
+/// Returns the name of the subdirectory storing the history of `root` scanned on `host`.
+///
+/// The name contains a readable form of the host and path,
+/// followed by a hash of both to tell apart paths that sanitize to the same string.
+pub fn name(root: &Path, host: Option<&str>) -> String {
+    let root = root.to_string_lossy();
+    let host = host.unwrap_or("unknown");
+
+    let mut path = sanitize(&root);
+    if path.is_empty() {
+        path.push_str("root");
+    }
+    path.truncate(MAX_PATH_LEN);
+
+    let mut hash = Fnv1a::default();
+    hash.write(host.as_bytes());
+    hash.write(&[0]);
+    hash.write(root.as_bytes());
+
+    format!("{}_{}_{:08x}", sanitize(host), path, hash.0)
+}
+
+/// Returns the history directory of `root` scanned on `host`
+pub fn dir(history_dir: &Path, root: &Path, host: Option<&str>) -> PathBuf {
+    history_dir.join(name(root, host))
+}
+
+/// Lists the directories of all namespaces in a history directory.
+///
+/// The history directory itself is included first if it directly contains history files,
+/// since history files written before namespaces were introduced are stored there.
+pub async fn all(history_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    let mut has_history_files = false;
+
+    let mut iter = fs::read_dir(history_dir.to_path_buf()).await?;
+    while let Some(entry) = iter.next().await {
+        match entry {
+            Ok(entry) => {
+                if entry.path().is_dir() {
+                    dirs.push(entry.path());
+                } else if entry
+                    .file_name()
+                    .to_str()
+                    .map_or(false, |name| super::parse_file_name(name).is_some())
+                {
+                    has_history_files = true;
+                }
+            }
+            Err(err) => log::error!("Failed scanning history directory: {}", err),
+        }
+    }
+
+    dirs.sort();
+    if has_history_files {
+        dirs.insert(0, history_dir.to_path_buf());
+    }
+    Ok(dirs)
+}
+
+fn sanitize(str: &str) -> String {
+    let mut ret = String::with_capacity(str.len());
+    for c in str.chars() {
+        if c.is_ascii_alphanumeric() {
+            ret.push(c);
+        } else if !ret.ends_with('-') {
+            ret.push('-');
+        }
+    }
+    ret.trim_matches('-').to_string()
+}
+
+/// 32-bit FNV-1a, used instead of `DefaultHasher` because its output must be stable
+struct Fnv1a(u32);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0x811c_9dc5)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u32::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0193);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use chrono::offset::Local as LocalTz;
+use chrono::{Datelike, TimeZone};
+
+const DAY: i64 = 86400;
+
+/// Decides which history files are removed during rotation.
+///
+/// If `keep_last` or `gfs` is set, a file is kept if either of them selects it.
+/// `max_age_days` and `max_total_size` are then applied as limits on the kept files,
+/// with `max_total_size` applied last to remove the oldest remaining files.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    /// Remove files older than this number of days
+    pub max_age_days: Option<u32>,
+    /// Keep only this number of most recent files
+    pub keep_last: Option<usize>,
+    /// Keep one file per day for a week, per week for a month and per month for a year
+    pub gfs: bool,
+    /// Remove the oldest files until the total size is within this number of bytes
+    pub max_total_size: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// The time of the snapshot, in seconds since the Unix epoch
+    pub timestamp: i64,
+    /// The size of the file on disk
+    pub size: u64,
+}
+
+impl Policy {
+    /// Returns the candidates that should be removed.
+    ///
+    /// The newest candidate is never removed.
+    pub fn select(&self, mut candidates: Vec<Candidate>, now: i64) -> Vec<Candidate> {
+        candidates.sort_by_key(|candidate| -candidate.timestamp);
+
+        let selective = self.keep_last.is_some() || self.gfs;
+        let mut keep = vec![!selective; candidates.len()];
+
+        if let Some(n) = self.keep_last {
+            for flag in keep.iter_mut().take(n) {
+                *flag = true;
+            }
+        }
+
+        if self.gfs {
+            let mut buckets = HashSet::new();
+            for (i, candidate) in candidates.iter().enumerate() {
+                if let Some(bucket) = gfs_bucket(candidate.timestamp, now) {
+                    if buckets.insert(bucket) {
+                        keep[i] = true;
+                    }
+                }
+            }
+        }
+
+        if let Some(days) = self.max_age_days {
+            for (i, candidate) in candidates.iter().enumerate() {
+                if now - candidate.timestamp > i64::from(days) * DAY {
+                    keep[i] = false;
+                }
+            }
+        }
+
+        if let Some(max) = self.max_total_size {
+            let mut total = 0;
+            for (i, candidate) in candidates.iter().enumerate() {
+                if !keep[i] {
+                    continue;
+                }
+                total += candidate.size;
+                if total > max {
+                    keep[i] = false;
+                }
+            }
+        }
+
+        if let Some(flag) = keep.first_mut() {
+            *flag = true;
+        }
+
+        candidates
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .map(|(candidate, _)| candidate)
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Bucket {
+    Day(i32, u32),
+    Week(i32, u32),
+    Month(i32, u32),
+}
+
+/// Returns the grandfather-father-son bucket of a snapshot,
+/// or `None` if it is too old to be kept.
+///
+/// Since candidates are visited from newest to oldest,
+/// the newest snapshot in each bucket is kept.
+fn gfs_bucket(timestamp: i64, now: i64) -> Option<Bucket> {
+    let age = now - timestamp;
+    let date = LocalTz.timestamp(timestamp, 0);
+    if age <= 7 * DAY {
+        Some(Bucket::Day(date.year(), date.ordinal()))
+    } else if age <= 31 * DAY {
+        let week = date.iso_week();
+        Some(Bucket::Week(week.year(), week.week()))
+    } else if age <= 366 * DAY {
+        Some(Bucket::Month(date.year(), date.month()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noon of the current local day, so that snapshots a whole number of days apart
+    /// fall on different dates
+    fn noon() -> i64 {
+        LocalTz::now().date().and_hms(12, 0, 0).timestamp()
+    }
+
+    /// One snapshot per day for `days` days, from newest to oldest, each of 10 bytes
+    fn daily(now: i64, days: i64) -> Vec<Candidate> {
+        (0..days)
+            .map(|day| Candidate {
+                path: PathBuf::from(format!("{}", day)),
+                timestamp: now - day * DAY,
+                size: 10,
+            })
+            .collect()
+    }
+
+    fn removed_days(removed: &[Candidate]) -> Vec<i64> {
+        let mut days: Vec<i64> = removed
+            .iter()
+            .map(|candidate| candidate.path.to_str().unwrap().parse().unwrap())
+            .collect();
+        days.sort();
+        days
+    }
+
+    #[test]
+    fn keep_last() {
+        let now = noon();
+        let policy = Policy {
+            keep_last: Some(3),
+            ..Default::default()
+        };
+        let removed = policy.select(daily(now, 10), now);
+        assert_eq!(removed_days(&removed), (3..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn max_age() {
+        let now = noon();
+        let policy = Policy {
+            max_age_days: Some(5),
+            ..Default::default()
+        };
+        let removed = policy.select(daily(now, 10), now);
+        assert_eq!(removed_days(&removed), (6..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn gfs_keeps_newest_per_day() {
+        let now = noon();
+        let mut candidates = daily(now, 3);
+        candidates.push(Candidate {
+            path: PathBuf::from("100"),
+            timestamp: now - DAY - 3600,
+            size: 10,
+        });
+        let policy = Policy {
+            gfs: true,
+            ..Default::default()
+        };
+        let removed = policy.select(candidates, now);
+        assert_eq!(removed_days(&removed), vec![100]);
+    }
+
+    #[test]
+    fn keep_last_and_gfs_are_a_union() {
+        let now = noon();
+        let policy = Policy {
+            keep_last: Some(5),
+            gfs: true,
+            ..Default::default()
+        };
+        let removed = removed_days(&policy.select(daily(now, 400), now));
+
+        // the last 5 and the daily keepers of the last week are all kept
+        for day in 0..=7 {
+            assert!(!removed.contains(&day), "day {} was removed", day);
+        }
+        // there are weekly and monthly keepers older than the last 5
+        let kept = 400 - removed.len();
+        assert!(kept > 8 + 10, "only {} snapshots were kept", kept);
+        assert!((32..=366).any(|day| !removed.contains(&day)));
+        // nothing older than a year is kept by gfs
+        assert!((367..400).all(|day| removed.contains(&day)));
+    }
+
+    #[test]
+    fn max_age_limits_gfs() {
+        let now = noon();
+        let policy = Policy {
+            gfs: true,
+            max_age_days: Some(10),
+            ..Default::default()
+        };
+        let removed = removed_days(&policy.select(daily(now, 100), now));
+        assert!((11..100).all(|day| removed.contains(&day)));
+        assert!((0..=7).all(|day| !removed.contains(&day)));
+    }
+
+    #[test]
+    fn max_total_size_removes_oldest() {
+        let now = noon();
+        let policy = Policy {
+            keep_last: Some(5),
+            max_total_size: Some(25),
+            ..Default::default()
+        };
+        let removed = policy.select(daily(now, 10), now);
+        assert_eq!(removed_days(&removed), (2..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn newest_is_never_removed() {
+        let now = noon();
+        let policy = Policy {
+            max_age_days: Some(1),
+            ..Default::default()
+        };
+        let candidates = vec![Candidate {
+            path: PathBuf::from("0"),
+            timestamp: now - 10 * DAY,
+            size: 10,
+        }];
+        assert!(policy.select(candidates, now).is_empty());
+    }
+}
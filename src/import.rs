@@ -0,0 +1,133 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::borrow::Cow;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use crate::{cli, crawl, history};
+
+mod du;
+mod find;
+pub mod ncdu;
+mod paths;
+
+/// The formats of disk usage reports from other tools that can be imported
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    /// The JSON dump format of ncdu, as written by `ncdu -o`
+    Ncdu,
+    /// The output of `du -ab`
+    Du,
+    /// The output of `find -printf '%s %k %p\n'`
+    Find,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "ncdu" => Ok(Format::Ncdu),
+            "du" => Ok(Format::Du),
+            "find" => Ok(Format::Find),
+            _ => Err(format!("Unknown import format {:?}", str)),
+        }
+    }
+}
+
+/// A report converted from another tool
+pub struct Imported {
+    /// The path of the scanned directory, if recorded by the tool
+    pub root: Option<PathBuf>,
+    /// The time of the scan in seconds since the Unix epoch, if recorded by the tool
+    pub timestamp: Option<i64>,
+    pub tree: crawl::Node,
+}
+
+/// Imports a report and stores it in history unless `--no-write` is passed
+pub async fn run(args: &cli::ImportArgs, history_dir: &Path) -> Result {
+    let data = if args.input == Path::new("-") {
+        let mut data = vec![];
+        io::stdin().read_to_end(&mut data)?;
+        data
+    } else {
+        tokio::fs::read(args.input.clone()).await?
+    };
+
+    let ctx = crawl::ExploreContext::default();
+    let imported = match args.format {
+        Format::Ncdu => ncdu::read(&data, args.shake(), &ctx)?,
+        Format::Du => du::read(&data, args.shake(), &ctx)?,
+        Format::Find => find::read(&data, args.shake(), &ctx)?,
+    };
+
+    let root = match (&args.root, imported.root) {
+        (Some(root), _) => root.clone(),
+        (None, Some(root)) => root,
+        (None, None) => {
+            return Err(make_err(
+                "The input does not record the scanned directory, pass it with --root",
+            ))
+        }
+    };
+    let meta = history::ReportMeta {
+        timestamp: imported
+            .timestamp
+            .unwrap_or_else(|| chrono::Local::now().timestamp()),
+        root,
+        host: args.host.clone().or_else(history::local_host),
+        shake: args.shake(),
+        duration_ms: 0,
+    };
+
+    if args.no_write {
+        log::info!("{} can be imported", args.input.display());
+        return Ok(());
+    }
+    let report = history::Report {
+        meta,
+        tree: imported.tree,
+    };
+    let dir = history::namespace_dir(history_dir, &report.meta);
+    history::write(&report, &dir, args.history_format()).await?;
+    match &args.host {
+        Some(host) => log::info!(
+            "Imported {}, view it with `fstree serve --root {} --snapshot-host {} latest`",
+            args.input.display(),
+            report.meta.root.display(),
+            host,
+        ),
+        None => log::info!(
+            "Imported {}, view it with `fstree serve --root {} latest`",
+            args.input.display(),
+            report.meta.root.display(),
+        ),
+    }
+    Ok(())
+}
+
+/// Returns the pooled extension of a file name
+fn extension(name: &str, ctx: &crawl::ExploreContext) -> crawl::StringRef {
+    ctx.intern(
+        Path::new(name)
+            .extension()
+            .map_or(Cow::Borrowed(""), |ext| ext.to_string_lossy()),
+    )
+}
@@ -0,0 +1,84 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::PathBuf;
+
+use super::paths::{self, DirSize};
+use super::Imported;
+use crate::crawl::{ExploreContext, Size};
+
+/// Reads the output of `du -ab`, where each line is a size in bytes and a path separated by a tab.
+///
+/// `du -b` only reports apparent sizes, which are used as the real sizes too.
+pub fn read(data: &[u8], shake: u64, ctx: &ExploreContext) -> Result<Imported> {
+    let text = String::from_utf8_lossy(data);
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(2, '\t');
+        let size = paths::parse_field(fields.next(), i + 1, "a size in bytes")?;
+        let path = fields
+            .next()
+            .ok_or_else(|| make_err(format!("Line {}: expected a tab before the path", i + 1)))?;
+        let size = Size {
+            real: size,
+            content: size,
+        };
+        entries.push((PathBuf::from(path), size));
+    }
+    paths::build(entries, DirSize::Total, shake, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const SAMPLE: &str = "4000\t/data/a/x.log
+100\t/data/a/y
+8196\t/data/a
+50\t/data/b c
+12342\t/data
+";
+
+    #[test]
+    fn sample() {
+        let imported = read(SAMPLE.as_bytes(), 0, &ExploreContext::default()).unwrap();
+        assert_eq!(imported.root, Some(PathBuf::from("/data")));
+
+        let total = imported.tree.total();
+        assert_eq!(total.size().real, 12342);
+        assert_eq!(total.files.count, 3);
+        // the sizes of directories exclude the entries listed under them
+        assert_eq!(total.dirs.count, 2);
+        assert_eq!(total.dirs.size.real, 8192);
+
+        let file = imported.tree.find(Path::new("a/x.log")).unwrap();
+        assert_eq!(file.total().size().real, 4000);
+        assert!(imported.tree.find(Path::new("b c")).is_some());
+    }
+
+    #[test]
+    fn malformed() {
+        let ctx = ExploreContext::default();
+        assert!(read(b"abc\t/data\n", 0, &ctx).is_err());
+        assert!(read(b"100 /data\n", 0, &ctx).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::path::PathBuf;
+
+use super::paths::{self, DirSize};
+use super::Imported;
+use crate::crawl::{ExploreContext, Size};
+
+/// Reads the output of `find -printf '%s %k %p\n'`,
+/// where each line is the size in bytes, the disk usage in KiB and the path.
+pub fn read(data: &[u8], shake: u64, ctx: &ExploreContext) -> Result<Imported> {
+    let text = String::from_utf8_lossy(data);
+    let mut entries = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, ' ');
+        let content = paths::parse_field(fields.next(), i + 1, "a size in bytes")?;
+        let blocks = paths::parse_field(fields.next(), i + 1, "a disk usage in KiB")?;
+        let path = fields
+            .next()
+            .ok_or_else(|| make_err(format!("Line {}: expected a path", i + 1)))?;
+        let size = Size {
+            real: blocks * 1024,
+            content,
+        };
+        entries.push((PathBuf::from(path), size));
+    }
+    paths::build(entries, DirSize::Own, shake, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const SAMPLE: &str = "4096 4 /data
+4096 4 /data/a
+4000 4 /data/a/x.log
+100 4 /data/a/y
+10 4 /data/a/with space
+";
+
+    #[test]
+    fn sample() {
+        let imported = read(SAMPLE.as_bytes(), 0, &ExploreContext::default()).unwrap();
+        assert_eq!(imported.root, Some(PathBuf::from("/data")));
+
+        let total = imported.tree.total();
+        assert_eq!(total.files.count, 3);
+        assert_eq!(total.dirs.count, 2);
+        // real sizes come from the disk usage in KiB
+        assert_eq!(total.size().real, 5 * 4 * 1024);
+        assert_eq!(total.size().content, 4096 * 2 + 4000 + 100 + 10);
+
+        let file = imported.tree.find(Path::new("a/with space")).unwrap();
+        assert_eq!(file.total().size().content, 10);
+    }
+
+    #[test]
+    fn malformed() {
+        let ctx = ExploreContext::default();
+        assert!(read(b"4096 /data\n", 0, &ctx).is_err());
+        assert!(read(b"4096 4\n", 0, &ctx).is_err());
+    }
+}
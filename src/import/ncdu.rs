@@ -0,0 +1,198 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use serde_json::{Map, Value};
+
+use super::{extension, Imported};
+use crate::crawl::{self, ExploreContext, Node, Size};
+
+/// The major version of the ncdu dump format that can be read
+const MAJOR_VERSION: u64 = 1;
+
+/// Reads an ncdu JSON dump, as written by `ncdu -o`.
+///
+/// Excluded entries are skipped, since ncdu does not record their sizes.
+/// Hard links are counted once for each link.
+pub fn read(data: &[u8], shake: u64, ctx: &ExploreContext) -> Result<Imported> {
+    let value: Value = serde_json::from_slice(data).map_err(make_err)?;
+    let dump = match value.as_array() {
+        Some(dump) if dump.len() >= 4 => dump,
+        _ => {
+            return Err(make_err(
+                "Not an ncdu dump: expected an array of 4 elements",
+            ))
+        }
+    };
+    match dump[0].as_u64() {
+        Some(MAJOR_VERSION) => (),
+        Some(version) => {
+            return Err(make_err(format!(
+                "Unsupported ncdu dump major version {}",
+                version
+            )))
+        }
+        None => return Err(make_err("Not an ncdu dump: missing major version")),
+    }
+    let timestamp = dump[2].get("timestamp").and_then(Value::as_i64);
+
+    let root = dump[3]
+        .as_array()
+        .and_then(|dir| dir.first())
+        .and_then(|info| info.get("name"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| make_err("Not an ncdu dump: the root is not a directory"))?;
+    let root = PathBuf::from(root);
+    let root_name = ctx.intern(
+        root.file_name()
+            .map_or(Cow::Borrowed("/"), |name| name.to_string_lossy()),
+    );
+
+    let tree = match read_node(&dump[3], shake, ctx)? {
+        Some(Node::Dir {
+            children, stats, ..
+        }) => Node::Dir {
+            name: root_name,
+            children,
+            stats,
+        },
+        _ => return Err(make_err("Not an ncdu dump: the root is excluded")),
+    };
+
+    Ok(Imported {
+        root: Some(root),
+        timestamp,
+        tree,
+    })
+}
+
+/// Converts an ncdu entry, returning `None` if it is excluded
+fn read_node(value: &Value, shake: u64, ctx: &ExploreContext) -> Result<Option<Node>> {
+    let (info, entries) = match value {
+        Value::Array(dir) => match dir.split_first() {
+            Some((Value::Object(info), entries)) => (info, Some(entries)),
+            _ => return Err(make_err("Malformed ncdu dump: directory without info")),
+        },
+        Value::Object(info) => (info, None),
+        _ => return Err(make_err("Malformed ncdu dump: unexpected entry")),
+    };
+
+    if info.contains_key("excluded") {
+        return Ok(None);
+    }
+    let name = info
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| make_err("Malformed ncdu dump: entry without name"))?;
+    let read_error = flag(info, "read_error");
+
+    let node = match entries {
+        Some(entries) => {
+            let mut children = Vec::with_capacity(entries.len());
+            for entry in entries {
+                if let Some(child) = read_node(entry, shake, ctx)? {
+                    children.push(child);
+                }
+            }
+            if read_error {
+                children.push(Node::Error {
+                    name: None,
+                    error: format!("Error reading {} reported by ncdu", name).into(),
+                });
+            }
+            crawl::dir_node(shake, ctx.intern(name), size(info), children)
+        }
+        None if read_error => Node::Error {
+            name: Some(ctx.intern(name)),
+            error: "Error reading file reported by ncdu".to_string().into(),
+        },
+        None => {
+            let extension = extension(name, ctx);
+            let name = ctx.intern(name);
+            let size = size(info);
+            if flag(info, "notreg") {
+                Node::Other {
+                    name,
+                    extension,
+                    size,
+                }
+            } else {
+                Node::File {
+                    name,
+                    extension,
+                    size,
+                }
+            }
+        }
+    };
+    Ok(Some(node))
+}
+
+fn size(info: &Map<String, Value>) -> Size {
+    let get = |key: &str| info.get(key).and_then(Value::as_u64).unwrap_or(0);
+    Size {
+        real: get("dsize"),
+        content: get("asize"),
+    }
+}
+
+fn flag(info: &Map<String, Value>, key: &str) -> bool {
+    info.get(key).and_then(Value::as_bool).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const SAMPLE: &str = r#"[1, 2, {"progname": "ncdu", "progver": "1.15", "timestamp": 1600000000},
+[{"name": "/data", "asize": 4096, "dsize": 4096},
+    {"name": "x.log", "asize": 4000, "dsize": 4096},
+    [{"name": "sub", "asize": 4096, "dsize": 4096},
+        {"name": "y", "asize": 100, "dsize": 4096}],
+    {"name": "skipped", "excluded": "pattern"},
+    {"name": "fifo", "notreg": true},
+    {"name": "locked", "read_error": true}
+]]"#;
+
+    #[test]
+    fn sample() {
+        let imported = read(SAMPLE.as_bytes(), 0, &ExploreContext::default()).unwrap();
+        assert_eq!(imported.root, Some(PathBuf::from("/data")));
+        assert_eq!(imported.timestamp, Some(1_600_000_000));
+
+        let total = imported.tree.total();
+        assert_eq!(total.size().real, 4 * 4096);
+        assert_eq!(total.size().content, 4096 * 2 + 4000 + 100);
+        assert_eq!(total.files.count, 2);
+        assert_eq!(total.dirs.count, 2);
+        assert_eq!(total.others.count, 1);
+        assert_eq!(total.errors, 1);
+
+        assert!(imported.tree.find(Path::new("sub/y")).is_some());
+        assert!(imported.tree.find(Path::new("skipped")).is_none());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let data = br#"[2, 0, {}, [{"name": "/"}]]"#;
+        assert!(read(data, 0, &ExploreContext::default()).is_err());
+    }
+}
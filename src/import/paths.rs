@@ -0,0 +1,144 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::collections::BTreeMap;
+use std::path::{Component, PathBuf};
+
+use super::{extension, Imported};
+use crate::crawl::{self, ExploreContext, Node, Size};
+
+/// What the size listed for a directory includes
+#[derive(Debug, Clone, Copy)]
+pub enum DirSize {
+    /// Only the directory itself, as listed by `find`
+    Own,
+    /// The directory and everything in it, as listed by `du`
+    Total,
+}
+
+#[derive(Default)]
+struct Tree {
+    size: Size,
+    children: BTreeMap<String, Tree>,
+}
+
+/// Builds a report from a flat listing of paths and their sizes.
+///
+/// The listing does not record file types,
+/// so paths with entries under them are directories and all other paths are files.
+/// The path that all other paths are under is the root.
+pub fn build(
+    entries: Vec<(PathBuf, Size)>,
+    dir_size: DirSize,
+    shake: u64,
+    ctx: &ExploreContext,
+) -> Result<Imported> {
+    let root = match entries
+        .iter()
+        .min_by_key(|(path, _)| path.components().count())
+    {
+        Some((root, _)) => root.clone(),
+        None => return Err(make_err("The input does not list any paths")),
+    };
+
+    let mut tree = Tree::default();
+    for (path, size) in entries {
+        let relative = path.strip_prefix(&root).map_err(|_| {
+            make_err(format!(
+                "{} is not under {}, only one directory can be imported",
+                path.display(),
+                root.display()
+            ))
+        })?;
+
+        let mut node = &mut tree;
+        for component in relative.components() {
+            match component {
+                Component::Normal(name) => {
+                    node = node
+                        .children
+                        .entry(name.to_string_lossy().into_owned())
+                        .or_default();
+                }
+                Component::CurDir => (),
+                _ => return Err(make_err(format!("Unexpected path {}", path.display()))),
+            }
+        }
+        node.size = size;
+    }
+
+    let name = root
+        .file_name()
+        .map_or_else(|| root.to_string_lossy(), |name| name.to_string_lossy());
+    let (tree, _) = to_node(&name, tree, dir_size, shake, ctx);
+    let tree = match tree {
+        Node::Dir { .. } => tree,
+        _ => crawl::dir_node(shake, ctx.intern(name), Size::default(), vec![tree]),
+    };
+
+    Ok(Imported {
+        root: if root.is_absolute() { Some(root) } else { None },
+        timestamp: None,
+        tree,
+    })
+}
+
+/// Converts a tree into a node, also returning its total size
+fn to_node(
+    name: &str,
+    tree: Tree,
+    dir_size: DirSize,
+    shake: u64,
+    ctx: &ExploreContext,
+) -> (Node, Size) {
+    if tree.children.is_empty() {
+        let node = Node::File {
+            name: ctx.intern(name),
+            extension: extension(name, ctx),
+            size: tree.size,
+        };
+        return (node, tree.size);
+    }
+
+    let mut children = Vec::with_capacity(tree.children.len());
+    let mut children_size = Size::default();
+    for (name, child) in tree.children {
+        let (child, size) = to_node(&name, child, dir_size, shake, ctx);
+        children.push(child);
+        children_size += size;
+    }
+
+    let own = match dir_size {
+        DirSize::Own => tree.size,
+        DirSize::Total => Size {
+            real: tree.size.real.saturating_sub(children_size.real),
+            content: tree.size.content.saturating_sub(children_size.content),
+        },
+    };
+    let mut total = own;
+    total += children_size;
+    let node = crawl::dir_node(shake, ctx.intern(name), own, children);
+    (node, total)
+}
+
+/// Parses a decimal number from a field of a line
+pub fn parse_field(field: Option<&str>, line: usize, what: &str) -> Result<u64> {
+    field
+        .and_then(|field| field.trim().parse().ok())
+        .ok_or_else(|| make_err(format!("Line {}: expected {}", line, what)))
+}
@@ -16,21 +16,46 @@
 #[allow(unused_imports)]
 use crate::result::{make_err, Result};
 
-use std::borrow::Cow;
 use std::env;
+use std::io;
+#[cfg(feature = "history")]
+use std::path::Path;
+use std::process;
 use std::time::{Duration, Instant};
 
-use cfg_if::cfg_if;
 use futures_util::future::{self, Either};
 use tokio::timer;
 
 mod cli;
+mod config;
 mod crawl;
+mod quota;
 mod result;
+mod tree;
 
+#[cfg(feature = "history")]
+mod check;
+#[cfg(feature = "history")]
+mod diff;
+#[cfg(feature = "history")]
+mod export;
+#[cfg(feature = "history")]
+mod forecast;
 #[cfg(feature = "history")]
 mod history;
+#[cfg(feature = "history")]
+mod import;
+#[cfg(feature = "history")]
+mod render;
+#[cfg(feature = "history")]
+mod summary;
+#[cfg(feature = "history")]
+mod svg;
+#[cfg(feature = "history")]
+mod trend;
 
+#[cfg(feature = "tui")]
+mod tui;
 #[cfg(feature = "web")]
 mod web;
 
@@ -40,41 +65,131 @@ async fn main() -> Result {
     pretty_env_logger::init();
 
     let args = cli::read()?;
+    #[cfg(feature = "history")]
+    let history_dir = args.history_dir();
+    #[cfg(feature = "history")]
+    let default_root = Path::new(cli::DEFAULT_ROOT);
 
-    if !args.dir.is_dir() {
-        Err(make_err(
-            format!("{}: not a directory", args.dir.display()).as_ref(),
-        ))?
-    }
-
-    cfg_if! {
-        if #[cfg(feature = "web")] {
-            if args.web_only {
-                if args.no_web {
-                    return Err(make_err("--web-only and --no-web are contradictory arguments"));
+    match &args.command {
+        cli::Command::Scan(scan_args) => {
+            let scanned = scan(&args, scan_args).await?;
+            let tree = scanned.tree;
+            let root = scan_args.dir().canonicalize()?;
+            if !scan_args.quota.is_empty() {
+                // standard output is reserved for the report, so the outcomes are logged
+                let outcomes = quota::evaluate(&scan_args.quota, &root, &tree, scan_args.shake())?;
+                for outcome in &outcomes {
+                    if outcome.passed {
+                        log::info!("Quota {}", outcome);
+                    } else {
+                        log::error!("Quota {}", outcome);
+                    }
                 }
-                web::run(None, &args.host, args.port)?;
-            }else {
-                let tree = scan(&args).await?;
-                if !args.no_web {
-                    web::run(Some(tree), &args.host, args.port)?;
+                if outcomes.iter().any(|outcome| !outcome.passed) {
+                    process::exit(1);
+                }
+            }
+            #[cfg(feature = "history")]
+            {
+                if scan_args.json.is_some() {
+                    return Ok(());
                 }
             }
-        } else {
-            scan(&args).await?;
+            #[cfg(feature = "tui")]
+            {
+                if scan_args.tui {
+                    #[allow(unused_mut)]
+                    let mut browser = tui::Browser::new(root.clone(), tree);
+                    #[cfg(feature = "history")]
+                    {
+                        browser.namespace_dir =
+                            Some(history::local_namespace_dir(&history_dir, &root));
+                    }
+                    return browser.run().await;
+                }
+            }
+            #[cfg(feature = "web")]
+            {
+                if !scan_args.no_web {
+                    let context = web::Context {
+                        current: Some(tree),
+                        #[cfg(feature = "history")]
+                        forecasts: scanned.forecasts,
+                    };
+                    return web::run(context, scan_args.web.host(), scan_args.web.port());
+                }
+            }
+            if !scan_args.no_tree() {
+                let stdout = io::stdout();
+                let options = scan_args.tree.options();
+                tree::write(&tree, &root.to_string_lossy(), &options, &mut stdout.lock())?;
+            }
+        }
+        #[cfg(feature = "web")]
+        cli::Command::Serve(serve_args) => serve(&args, serve_args).await?,
+        #[cfg(feature = "history")]
+        cli::Command::Tree(tree_args) => tree::run(tree_args, &history_dir, default_root).await?,
+        #[cfg(all(feature = "tui", feature = "history"))]
+        cli::Command::Browse(browse_args) => {
+            tui::run(browse_args, &history_dir, default_root).await?
         }
+        #[cfg(feature = "history")]
+        cli::Command::History(cmd) => history::run_command(cmd, &history_dir, default_root).await?,
+        #[cfg(feature = "history")]
+        cli::Command::Diff(diff_args) => diff::run(diff_args, &history_dir, default_root).await?,
+        #[cfg(feature = "history")]
+        cli::Command::Trend(trend_args) => {
+            trend::run(trend_args, &history_dir, default_root).await?
+        }
+        #[cfg(feature = "history")]
+        cli::Command::Check(check_args) => {
+            if !check::run(check_args, &history_dir, default_root).await? {
+                process::exit(1);
+            }
+        }
+        #[cfg(feature = "history")]
+        cli::Command::Export(export_args) => {
+            export::run(export_args, &history_dir, default_root).await?
+        }
+        #[cfg(feature = "history")]
+        cli::Command::Import(import_args) => import::run(import_args, &history_dir).await?,
+        #[cfg(feature = "history")]
+        cli::Command::Render(cmd) => render::run(cmd, &history_dir, default_root).await?,
+        cli::Command::Config(cli::ConfigCommand::Show) => args.config.show(),
     }
 
     Ok(())
 }
 
-async fn scan(args: &cli::CommandArgs) -> Result<crawl::Node> {
-    log::info!("Scanning {}", args.dir.display());
+/// The result of a scan
+struct Scanned {
+    tree: crawl::Node,
+    /// Forecasts from the history of the scanned directory
+    #[cfg(feature = "history")]
+    #[cfg_attr(not(feature = "web"), allow(dead_code))]
+    forecasts: Vec<forecast::Forecast>,
+}
+
+#[allow(unused_variables)]
+async fn scan(args: &cli::CommandArgs, scan_args: &cli::ScanArgs) -> Result<Scanned> {
+    log::info!("Scanning {}", scan_args.dir().display());
+    #[cfg(feature = "history")]
+    let timestamp = chrono::Local::now().timestamp();
     let epoch = Instant::now();
-    let ctx = &crawl::ExploreContext::default();
-    let mut ftree = Box::pin(crawl::explore(args.dir.clone(), args.shake.0, ctx));
+    let ctx = &crawl::ExploreContext {
+        excludes: scan_args
+            .exclude
+            .iter()
+            .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone()))
+            .collect(),
+        ..Default::default()
+    };
+    let mut ftree = Box::pin(crawl::explore(
+        scan_args.dir().to_path_buf(),
+        scan_args.shake(),
+        ctx,
+    ));
 
-    #[allow(unused_variables)]
     let tree = loop {
         let timeout = timer::delay_for(Duration::from_millis(100));
         match future::select(timeout, ftree).await {
@@ -90,28 +205,121 @@ async fn scan(args: &cli::CommandArgs) -> Result<crawl::Node> {
     };
 
     #[cfg(feature = "history")]
-    {
-        if !args.no_write {
-            let history_dir = if let Some(dir) = &args.history_dir {
-                Cow::Borrowed(dir)
-            } else {
-                Cow::Owned(
-                    dirs::home_dir()
-                        .expect("Failed to get home directory")
-                        .join(".fstree/history"),
-                )
-            };
-            history::write(&tree, &history_dir).await?;
+    let scanned = {
+        let meta = history::ReportMeta::new(
+            timestamp,
+            scan_args.dir().canonicalize()?,
+            scan_args.shake(),
+            epoch.elapsed(),
+        );
+        let report = history::Report { meta, tree };
+
+        if let Some(path) = &scan_args.prometheus {
+            if let Err(err) =
+                export::prometheus::write_file(&report, &scan_args.metrics.options(), path)
+            {
+                log::error!("Failed to write metrics to {}: {}", path.display(), err);
+            }
+        }
 
-            if !args.no_rotate {
-                if let Err(err) = history::rotate(&history_dir, args.rotate_days).await {
+        if let Some(path) = &scan_args.json {
+            export::write_json(
+                &report,
+                scan_args.json_mode,
+                &Default::default(),
+                Some(path),
+            )?;
+            return Ok(Scanned {
+                tree: report.tree,
+                forecasts: vec![],
+            });
+        }
+
+        let history_dir = history::namespace_dir(&args.history_dir(), &report.meta);
+        if !scan_args.no_write {
+            history::write(&report, &history_dir, scan_args.history_format()).await?;
+
+            if !scan_args.no_rotate {
+                if let Err(err) =
+                    history::rotate(&history_dir, &scan_args.retention.policy(), false).await
+                {
                     log::error!("Error rotating logs: {}", err);
                 }
             }
+            history::warn_flat_files(&args.history_dir()).await;
         }
-    }
 
-    Ok(tree)
+        let forecasts = forecasts(&history_dir).await;
+        for forecast in &forecasts {
+            log::info!("{}", forecast);
+        }
+
+        if let Some(style) = scan_args.summary {
+            let previous = summary::previous(&history_dir, report.meta.timestamp).await;
+            let options = summary::Options {
+                style,
+                top: scan_args.summary_top,
+            };
+            let stdout = io::stdout();
+            summary::write(&report, previous.as_ref(), &options, &mut stdout.lock())?;
+        }
+
+        Scanned {
+            tree: report.tree,
+            forecasts,
+        }
+    };
+    #[cfg(not(feature = "history"))]
+    let scanned = Scanned { tree };
+
+    Ok(scanned)
+}
+
+/// Starts the web frontend, viewing a history snapshot if one is passed
+#[cfg(feature = "web")]
+#[allow(unused_variables)]
+async fn serve(args: &cli::CommandArgs, serve_args: &cli::ServeArgs) -> Result {
+    #[cfg(feature = "history")]
+    let context = match &serve_args.snapshot {
+        Some(snapshot) => {
+            let path = history::resolve(
+                &args.history_dir(),
+                &serve_args.namespace(),
+                Path::new(cli::DEFAULT_ROOT),
+                snapshot,
+            )
+            .await?;
+            let report = history::read(&path).await?;
+            let namespace_dir = history::namespace_dir(&args.history_dir(), &report.meta);
+            web::Context {
+                current: Some(report.tree),
+                forecasts: forecasts(&namespace_dir).await,
+            }
+        }
+        None => web::Context {
+            current: None,
+            forecasts: vec![],
+        },
+    };
+    #[cfg(not(feature = "history"))]
+    let context = web::Context { current: None };
+
+    web::run(context, serve_args.web.host(), serve_args.web.port())
+}
+
+/// Forecasts from a history namespace, without looking into subdirectories
+#[cfg(feature = "history")]
+async fn forecasts(namespace_dir: &Path) -> Vec<forecast::Forecast> {
+    if !namespace_dir.is_dir() {
+        return vec![];
+    }
+    match forecast::namespace(namespace_dir, &forecast::Options::default()).await {
+        Ok(forecasts) => forecasts,
+        Err(err) => {
+            log::warn!("Failed to forecast from history: {}", err);
+            vec![]
+        }
+    }
 }
 
 fn default_env() {
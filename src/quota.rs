@@ -0,0 +1,185 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use crate::cli;
+use crate::crawl::{FileTypeExt, Node};
+
+/// A limit on the real size of a path
+#[derive(Debug, Clone)]
+pub struct PathRule {
+    /// The path to check, either absolute or relative to the scanned directory
+    pub path: PathBuf,
+    pub max: u64,
+}
+
+impl FromStr for PathRule {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        let (path, max) = split_rule(str)?;
+        Ok(Self {
+            path: PathBuf::from(path),
+            max,
+        })
+    }
+}
+
+/// A limit on the total real size of files with an extension
+#[derive(Debug, Clone)]
+pub struct ExtensionRule {
+    /// The extension without the leading dot
+    pub extension: String,
+    pub max: u64,
+}
+
+impl FromStr for ExtensionRule {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        let (extension, max) = split_rule(str)?;
+        Ok(Self {
+            extension: extension.trim_start_matches('.').to_string(),
+            max,
+        })
+    }
+}
+
+/// Splits a rule in the form `name=size` at the last `=`
+fn split_rule(str: &str) -> ResultOf<(&str, u64), String> {
+    let pos = str
+        .rfind('=')
+        .ok_or_else(|| format!("{:?}: expected a rule in the form name=size", str))?;
+    let max = str[pos + 1..].parse::<cli::Byte>()?.0;
+    Ok((&str[..pos], max))
+}
+
+/// The result of evaluating a single rule
+#[derive(Debug)]
+pub struct Outcome {
+    pub passed: bool,
+    pub message: String,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed { "OK  " } else { "FAIL" };
+        write!(f, "{}  {}", status, self.message)
+    }
+}
+
+/// Evaluates the quota rules against a tree scanned from `root` with the shaking threshold `shake`
+pub fn evaluate(
+    args: &cli::QuotaArgs,
+    root: &Path,
+    tree: &Node,
+    shake: u64,
+) -> Result<Vec<Outcome>> {
+    let mut outcomes = vec![];
+
+    for rule in &args.max {
+        let relative = if rule.path.is_absolute() {
+            rule.path.strip_prefix(root).map_err(|_| {
+                make_err(format!(
+                    "{}: not in the scanned directory {}",
+                    rule.path.display(),
+                    root.display()
+                ))
+            })?
+        } else {
+            &rule.path
+        };
+        let outcome = match tree.find(relative) {
+            Some(node) => {
+                let size = node.total().size().real;
+                Outcome {
+                    passed: size <= rule.max,
+                    message: format!(
+                        "{}: {} of at most {}",
+                        root.join(relative).display(),
+                        cli::Byte(size),
+                        cli::Byte(rule.max)
+                    ),
+                }
+            }
+            // missing paths either do not exist or were smaller than the shaking threshold,
+            // which only proves the limit if the threshold is not above it
+            None if rule.max < shake => Outcome {
+                passed: false,
+                message: format!(
+                    "{}: not in the report, so it cannot be checked against {} \
+                     with the larger shaking threshold {}",
+                    root.join(relative).display(),
+                    cli::Byte(rule.max),
+                    cli::Byte(shake)
+                ),
+            },
+            None => Outcome {
+                passed: true,
+                message: format!(
+                    "{}: not in the report, so it is missing or below the shaking threshold",
+                    root.join(relative).display()
+                ),
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    let stats = tree.stats();
+
+    for rule in &args.max_ext {
+        let size: u64 = stats
+            .by_extension
+            .iter()
+            .filter(|(fte, _)| match fte {
+                FileTypeExt::File(ext) | FileTypeExt::Other(ext) => **ext == *rule.extension,
+                _ => false,
+            })
+            .map(|(_, typed)| typed.size().real)
+            .sum();
+        outcomes.push(Outcome {
+            passed: size <= rule.max,
+            message: format!(
+                ".{}: {} of at most {}",
+                rule.extension,
+                cli::Byte(size),
+                cli::Byte(rule.max)
+            ),
+        });
+    }
+
+    if let Some(max) = args.max_errors {
+        let errors = stats.total.errors;
+        outcomes.push(Outcome {
+            passed: errors <= max,
+            message: format!("{} errors of at most {}", errors, max),
+        });
+    }
+
+    Ok(outcomes)
+}
+
+/// Prints the outcomes of quota rules, returning whether all of them passed
+pub fn report(outcomes: &[Outcome]) -> bool {
+    for outcome in outcomes {
+        println!("{}", outcome);
+    }
+    outcomes.iter().all(|outcome| outcome.passed)
+}
@@ -0,0 +1,77 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::fs;
+use std::path::Path;
+
+use crate::{cli, history};
+
+mod treemap;
+
+pub async fn run(cmd: &cli::RenderCommand, history_dir: &Path, default_root: &Path) -> Result {
+    match cmd {
+        cli::RenderCommand::Treemap(args) => {
+            let path = history::resolve_snapshot(history_dir, &args.snapshot, default_root).await?;
+            let report = history::read(&path).await?;
+
+            let node = match &args.path {
+                Some(path) => report.tree.find(path).ok_or_else(|| {
+                    make_err(format!("{}: no such path in the snapshot", path.display()))
+                })?,
+                None => &report.tree,
+            };
+            let title = match &args.path {
+                Some(path) => report.meta.root.join(path),
+                None => report.meta.root.clone(),
+            };
+            let options = treemap::Options {
+                width: args.width,
+                height: args.height,
+                depth: args.depth,
+                weight: args.weight,
+            };
+
+            let mut svg = vec![];
+            treemap::write(node, &title.to_string_lossy(), &options, &mut svg)?;
+            write_image(&svg, &args.output)
+        }
+    }
+}
+
+/// Writes an SVG document to a file, rasterizing it if the file name ends with `.png`
+fn write_image(svg: &[u8], path: &Path) -> Result {
+    let png = path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("png"));
+    if !png {
+        fs::write(path, svg)?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "png")]
+    {
+        let svg = String::from_utf8_lossy(svg);
+        crate::svg::write_png(&svg, path)
+    }
+    #[cfg(not(feature = "png"))]
+    {
+        Err(make_err(
+            "PNG output requires fstree to be built with the png feature",
+        ))
+    }
+}
@@ -0,0 +1,313 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::{Ordering, Reverse};
+use std::io::Write;
+
+use crate::crawl::{FileTypeExt, Node};
+use crate::export::Weight;
+use crate::svg;
+
+const TITLE_HEIGHT: f64 = 28.;
+const HEADER_HEIGHT: f64 = 14.;
+const PADDING: f64 = 2.;
+const FONT_SIZE: f64 = 11.;
+/// Rectangles smaller than this number of pixels in either dimension are not subdivided
+const MIN_SIDE: f64 = 6.;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    /// The number of directory levels drawn below the root
+    pub depth: usize,
+    pub weight: Weight,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+/// Writes a squarified treemap of a node as an SVG document
+pub fn write(node: &Node, title: &str, options: &Options, w: &mut impl Write) -> Result {
+    let width = f64::from(options.width);
+    let height = f64::from(options.height);
+    svg::start(w, width, height)?;
+    svg::text(
+        w,
+        PADDING,
+        TITLE_HEIGHT - 9.,
+        width,
+        16.,
+        &format!(
+            "{} ({})",
+            title,
+            options.weight.display(options.weight.of(&node.total()))
+        ),
+    )?;
+    let rect = Rect {
+        x: 0.,
+        y: TITLE_HEIGHT,
+        w: width,
+        h: height - TITLE_HEIGHT,
+    };
+    write_node(node, title, rect, 0, options, w)?;
+    svg::end(w)
+}
+
+fn write_node(
+    node: &Node,
+    path: &str,
+    rect: Rect,
+    depth: usize,
+    options: &Options,
+    w: &mut impl Write,
+) -> Result {
+    let weight = options.weight.of(&node.total());
+    let label = format!("{} ({})", path, options.weight.display(weight));
+    let name = node.name().map_or("", |name| &**name);
+
+    let children = match node {
+        Node::Dir { children, .. } if depth < options.depth => children,
+        _ => {
+            svg::rect(w, (rect.x, rect.y, rect.w, rect.h), color(node), &label)?;
+            if rect.h >= FONT_SIZE + 2. {
+                svg::text(
+                    w,
+                    rect.x + 2.,
+                    rect.y + FONT_SIZE,
+                    rect.w - 4.,
+                    FONT_SIZE,
+                    name,
+                )?;
+            }
+            return Ok(());
+        }
+    };
+
+    svg::rect(w, (rect.x, rect.y, rect.w, rect.h), "#e0e0e0", &label)?;
+    let header = if rect.h > HEADER_HEIGHT * 3. {
+        svg::text(
+            w,
+            rect.x + 2.,
+            rect.y + FONT_SIZE,
+            rect.w - 4.,
+            FONT_SIZE,
+            name,
+        )?;
+        HEADER_HEIGHT
+    } else {
+        0.
+    };
+    let inner = Rect {
+        x: rect.x + PADDING,
+        y: rect.y + PADDING + header,
+        w: rect.w - PADDING * 2.,
+        h: rect.h - PADDING * 2. - header,
+    };
+    if inner.w < MIN_SIDE || inner.h < MIN_SIDE || weight == 0 {
+        return Ok(());
+    }
+
+    let mut children: Vec<(&Node, u64)> = children
+        .iter()
+        .map(|child| (child, options.weight.of(&child.total())))
+        .filter(|&(_, weight)| weight > 0)
+        .collect();
+    children.sort_by_key(|&(_, weight)| Reverse(weight));
+
+    // children are scaled by their share of the directory,
+    // leaving the space of shaken entries empty
+    let scale = inner.w * inner.h / weight as f64;
+    let areas: Vec<f64> = children
+        .iter()
+        .map(|&(_, weight)| weight as f64 * scale)
+        .collect();
+    for (&(child, _), rect) in children.iter().zip(squarify(&areas, inner)) {
+        let path = format!(
+            "{}/{}",
+            path.trim_end_matches('/'),
+            child.name().map_or("(error)", |name| &**name)
+        );
+        write_node(child, &path, rect, depth + 1, options, w)?;
+    }
+    Ok(())
+}
+
+/// Lays out areas sorted in descending order into a rectangle,
+/// keeping the aspect ratios of the resulting rectangles close to 1.
+///
+/// The areas may sum to less than the area of the rectangle,
+/// in which case the remaining space is left at the end.
+fn squarify(areas: &[f64], mut rect: Rect) -> Vec<Rect> {
+    let mut ret = Vec::with_capacity(areas.len());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = rect.w.min(rect.h);
+        let mut end = start + 1;
+        let mut ratio = worst_ratio(&areas[start..end], side);
+        while end < areas.len() {
+            let next = worst_ratio(&areas[start..=end], side);
+            if next.partial_cmp(&ratio) == Some(Ordering::Greater) {
+                break;
+            }
+            ratio = next;
+            end += 1;
+        }
+
+        let row = &areas[start..end];
+        let sum: f64 = row.iter().sum();
+        if rect.w >= rect.h {
+            let thickness = if rect.h > 0. { sum / rect.h } else { 0. };
+            let mut y = rect.y;
+            for &area in row {
+                let h = if thickness > 0. { area / thickness } else { 0. };
+                ret.push(Rect {
+                    x: rect.x,
+                    y,
+                    w: thickness,
+                    h,
+                });
+                y += h;
+            }
+            rect.x += thickness;
+            rect.w -= thickness;
+        } else {
+            let thickness = if rect.w > 0. { sum / rect.w } else { 0. };
+            let mut x = rect.x;
+            for &area in row {
+                let w = if thickness > 0. { area / thickness } else { 0. };
+                ret.push(Rect {
+                    x,
+                    y: rect.y,
+                    w,
+                    h: thickness,
+                });
+                x += w;
+            }
+            rect.y += thickness;
+            rect.h -= thickness;
+        }
+        start = end;
+    }
+    ret
+}
+
+/// Returns the largest aspect ratio among the rectangles of a row laid along a side
+fn worst_ratio(row: &[f64], side: f64) -> f64 {
+    let sum: f64 = row.iter().sum();
+    let max = row.iter().cloned().fold(0., f64::max);
+    let min = row.iter().cloned().fold(std::f64::INFINITY, f64::min);
+    let side2 = side * side;
+    let sum2 = sum * sum;
+    (side2 * max / sum2).max(sum2 / (side2 * min))
+}
+
+/// Returns the fill color of a node drawn without children,
+/// which is the color of its largest extension for directories
+fn color(node: &Node) -> &'static str {
+    match node {
+        Node::Dir { stats, .. } => stats
+            .by_extension
+            .iter()
+            .filter(|(fte, _)| *fte != FileTypeExt::Dir)
+            .max_by_key(|(_, typed)| typed.size().real)
+            .map_or("#b0b0b0", |(fte, _)| svg::category_color(fte)),
+        _ => node
+            .stats()
+            .by_extension
+            .first()
+            .map_or("#909090", |(fte, _)| svg::category_color(fte)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-6;
+
+    fn area(rect: &Rect) -> f64 {
+        rect.w * rect.h
+    }
+
+    fn contains(outer: &Rect, inner: &Rect) -> bool {
+        inner.x >= outer.x - EPSILON
+            && inner.y >= outer.y - EPSILON
+            && inner.x + inner.w <= outer.x + outer.w + EPSILON
+            && inner.y + inner.h <= outer.y + outer.h + EPSILON
+    }
+
+    fn overlap(a: &Rect, b: &Rect) -> f64 {
+        let w = (a.x + a.w).min(b.x + b.w) - a.x.max(b.x);
+        let h = (a.y + a.h).min(b.y + b.h) - a.y.max(b.y);
+        w.max(0.) * h.max(0.)
+    }
+
+    fn check_layout(areas: &[f64], parent: Rect) -> Vec<Rect> {
+        let rects = squarify(areas, parent);
+        assert_eq!(rects.len(), areas.len());
+        for (rect, &expected) in rects.iter().zip(areas) {
+            assert!((area(rect) - expected).abs() < EPSILON, "{:?}", rect);
+            assert!(
+                contains(&parent, rect),
+                "{:?} is outside {:?}",
+                rect,
+                parent
+            );
+        }
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(overlap(a, b) < EPSILON, "{:?} overlaps {:?}", a, b);
+            }
+        }
+        rects
+    }
+
+    #[test]
+    fn areas_sum_to_parent() {
+        let parent = Rect {
+            x: 10.,
+            y: 20.,
+            w: 600.,
+            h: 400.,
+        };
+        let areas = [96000., 48000., 48000., 24000., 12000., 12000.];
+        let rects = check_layout(&areas, parent);
+        let total: f64 = rects.iter().map(area).sum();
+        assert!((total - area(&parent)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn partial_fill() {
+        let parent = Rect {
+            x: 0.,
+            y: 0.,
+            w: 300.,
+            h: 500.,
+        };
+        let areas = [50000., 20000., 5000., 5000.];
+        let rects = check_layout(&areas, parent);
+        let total: f64 = rects.iter().map(area).sum();
+        assert!((total - 80000.).abs() < EPSILON);
+    }
+}
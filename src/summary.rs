@@ -0,0 +1,340 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::result::Result as ResultOf;
+use std::str::FromStr;
+
+use chrono::offset::Local as LocalTz;
+use chrono::TimeZone;
+
+use crate::cli::Byte;
+use crate::crawl::{Node, Size};
+use crate::diff;
+use crate::history::{self, Report};
+
+/// The markup of a summary
+#[derive(Debug, Clone, Copy)]
+pub enum Style {
+    Markdown,
+    Text,
+}
+
+impl FromStr for Style {
+    type Err = String;
+    fn from_str(str: &str) -> ResultOf<Self, Self::Err> {
+        match str {
+            "markdown" | "md" => Ok(Style::Markdown),
+            "text" | "txt" => Ok(Style::Text),
+            _ => Err(format!("Unknown summary style {:?}", str)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub style: Style,
+    /// The number of entries in each section
+    pub top: usize,
+}
+
+/// Reads the latest snapshot in a history directory taken before `timestamp`, if any
+pub async fn previous(dir: &Path, timestamp: i64) -> Option<Report> {
+    if !dir.is_dir() {
+        return None;
+    }
+    let entries = match history::list(dir).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::warn!("Failed to list history in {}: {}", dir.display(), err);
+            return None;
+        }
+    };
+    let entry = entries
+        .into_iter()
+        .filter(|entry| entry.timestamp < timestamp)
+        .max_by_key(|entry| entry.timestamp)?;
+    let path = dir.join(&entry.file);
+    match history::read(&path).await {
+        Ok(report) => Some(report),
+        Err(err) => {
+            log::warn!("Failed to read {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Writes a summary of a report,
+/// including the biggest changes since `previous` if it is passed
+pub fn write(
+    report: &Report,
+    previous: Option<&Report>,
+    options: &Options,
+    w: &mut impl Write,
+) -> Result {
+    let meta = &report.meta;
+    let total = report.tree.total();
+
+    let title = format!("fstree summary of {}", meta.root.display());
+    match options.style {
+        Style::Markdown => writeln!(w, "# {}", escape(&title))?,
+        Style::Text => writeln!(w, "{}\n{}", title, "=".repeat(title.chars().count()))?,
+    }
+    writeln!(w)?;
+    writeln!(
+        w,
+        "Scanned at {}{} in {:.1}s. Total {} in {} files and {} directories, with {} errors.",
+        format_time(meta.timestamp),
+        meta.host
+            .as_ref()
+            .map_or_else(String::new, |host| format!(" on {}", host)),
+        meta.duration_ms as f64 / 1000.,
+        Byte(total.size().real),
+        total.files.count,
+        total.dirs.count,
+        total.errors
+    )?;
+    writeln!(w)?;
+
+    let mut nodes = Nodes::default();
+    nodes.collect(&report.tree, &meta.root, true);
+
+    nodes.dirs.sort_by_key(|(_, size)| Reverse(size.real));
+    let mut table = Table::new(&["Size", "Directory"], &[true, false]);
+    for (path, size) in nodes.dirs.iter().take(options.top) {
+        table.push(vec![
+            Byte(size.real).to_string(),
+            path.display().to_string(),
+        ]);
+    }
+    table.write(w, options.style, "Largest directories")?;
+
+    nodes.files.sort_by_key(|(_, size)| Reverse(size.real));
+    let mut table = Table::new(&["Size", "File"], &[true, false]);
+    for (path, size) in nodes.files.iter().take(options.top) {
+        table.push(vec![
+            Byte(size.real).to_string(),
+            path.display().to_string(),
+        ]);
+    }
+    table.write(w, options.style, "Largest files")?;
+
+    let mut extensions = report.tree.stats().by_extension;
+    extensions.sort_by_key(|(_, typed)| Reverse(typed.size().real));
+    let total_size = total.size().real.max(1);
+    let mut table = Table::new(
+        &["Size", "Share", "Count", "Extension"],
+        &[true, true, true, false],
+    );
+    for (fte, typed) in extensions.iter().take(options.top) {
+        let size = typed.size().real;
+        table.push(vec![
+            Byte(size).to_string(),
+            format!("{:.1}%", size as f64 * 100. / total_size as f64),
+            (typed.files.count + typed.dirs.count + typed.others.count).to_string(),
+            fte.to_string(),
+        ]);
+    }
+    table.write(w, options.style, "Largest extensions")?;
+
+    let mut errors: Vec<_> = nodes.errors.into_iter().collect();
+    errors.sort_by_key(|(message, (count, _))| (Reverse(*count), message.clone()));
+    let listed: usize = errors.iter().map(|(_, (count, _))| count).sum();
+    let mut table = Table::new(&["Count", "Error", "Example"], &[true, false, false]);
+    for (message, (count, example)) in errors {
+        table.push(vec![
+            count.to_string(),
+            message,
+            example.display().to_string(),
+        ]);
+    }
+    if total.errors > listed {
+        table.push(vec![
+            (total.errors - listed).to_string(),
+            "(not retained in the snapshot)".to_string(),
+            String::new(),
+        ]);
+    }
+    table.write(w, options.style, "Errors")?;
+
+    if let Some(previous) = previous {
+        let diff = diff::diff(previous, report);
+        let mut table = Table::new(&["Change", "Kind", "Path"], &[true, false, false]);
+        for change in diff
+            .changes
+            .iter()
+            .filter(|change| change.delta() != 0)
+            .take(options.top)
+        {
+            table.push(vec![
+                diff::signed_size(change.delta()),
+                change.kind.name().to_string(),
+                diff::display_path(&meta.root, &change.path)
+                    .display()
+                    .to_string(),
+            ]);
+        }
+        let title = format!(
+            "Biggest changes since {}",
+            format_time(previous.meta.timestamp)
+        );
+        table.write(w, options.style, &title)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Nodes {
+    dirs: Vec<(PathBuf, Size)>,
+    files: Vec<(PathBuf, Size)>,
+    /// The number of errors and an example path for each error message
+    errors: HashMap<String, (usize, PathBuf)>,
+}
+
+impl Nodes {
+    fn collect(&mut self, node: &Node, path: &Path, root: bool) {
+        match node {
+            Node::Dir { children, .. } => {
+                if !root {
+                    self.dirs.push((path.to_path_buf(), node.total().size()));
+                }
+                for child in children {
+                    let path = match child.name() {
+                        Some(name) => path.join(&**name),
+                        None => path.to_path_buf(),
+                    };
+                    self.collect(child, &path, false);
+                }
+            }
+            Node::File { size, .. } => self.files.push((path.to_path_buf(), *size)),
+            Node::Error { error, .. } => {
+                let entry = self
+                    .errors
+                    .entry(error.to_string())
+                    .or_insert_with(|| (0, path.to_path_buf()));
+                entry.0 += 1;
+            }
+            _ => (),
+        }
+    }
+}
+
+struct Table {
+    headers: &'static [&'static str],
+    /// Whether each column is aligned to the right
+    right: &'static [bool],
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(headers: &'static [&'static str], right: &'static [bool]) -> Self {
+        Self {
+            headers,
+            right,
+            rows: vec![],
+        }
+    }
+
+    fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    fn write(&self, w: &mut impl Write, style: Style, title: &str) -> Result {
+        match style {
+            Style::Markdown => {
+                writeln!(w, "## {}", escape(title))?;
+                writeln!(w)?;
+                if self.rows.is_empty() {
+                    writeln!(w, "None.")?;
+                } else {
+                    writeln!(w, "| {} |", self.headers.join(" | "))?;
+                    let align: Vec<_> = self
+                        .right
+                        .iter()
+                        .map(|&right| if right { "---:" } else { "---" })
+                        .collect();
+                    writeln!(w, "| {} |", align.join(" | "))?;
+                    for row in &self.rows {
+                        let cells: Vec<_> = row.iter().map(|cell| escape(cell)).collect();
+                        writeln!(w, "| {} |", cells.join(" | "))?;
+                    }
+                }
+            }
+            Style::Text => {
+                writeln!(w, "{}\n{}", title, "-".repeat(title.chars().count()))?;
+                if self.rows.is_empty() {
+                    writeln!(w, "None.")?;
+                } else {
+                    let mut widths: Vec<_> = self.headers.iter().map(|h| h.len()).collect();
+                    for row in &self.rows {
+                        for (width, cell) in widths.iter_mut().zip(row) {
+                            *width = (*width).max(cell.chars().count());
+                        }
+                    }
+                    let headers: Vec<_> = self.headers.iter().map(|h| h.to_string()).collect();
+                    self.write_text_row(w, &headers, &widths)?;
+                    for row in &self.rows {
+                        self.write_text_row(w, row, &widths)?;
+                    }
+                }
+            }
+        }
+        writeln!(w)?;
+        Ok(())
+    }
+
+    fn write_text_row(&self, w: &mut impl Write, row: &[String], widths: &[usize]) -> Result {
+        let cells: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .zip(self.right)
+            .map(|((cell, &width), &right)| {
+                if right {
+                    format!("{:>width$}", cell, width = width)
+                } else {
+                    format!("{:<width$}", cell, width = width)
+                }
+            })
+            .collect();
+        writeln!(w, "{}", cells.join("  ").trim_end())?;
+        Ok(())
+    }
+}
+
+/// Escapes the characters that are special in Markdown text and tables
+fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]<>|#".contains(c) {
+            ret.push('\\');
+        }
+        ret.push(c);
+    }
+    ret
+}
+
+fn format_time(timestamp: i64) -> String {
+    LocalTz
+        .timestamp(timestamp, 0)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
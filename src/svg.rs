@@ -0,0 +1,147 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::io::Write;
+#[cfg(feature = "png")]
+use std::path::Path;
+
+use crate::crawl::FileTypeExt;
+
+/// Writes the opening tag of an SVG document
+pub fn start(w: &mut impl Write, width: f64, height: f64) -> Result {
+    writeln!(
+        w,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="Verdana, sans-serif">"#,
+        width, height
+    )?;
+    Ok(())
+}
+
+pub fn end(w: &mut impl Write) -> Result {
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
+/// Writes a rectangle with a tooltip
+pub fn rect(
+    w: &mut impl Write,
+    (x, y, width, height): (f64, f64, f64, f64),
+    fill: &str,
+    title: &str,
+) -> Result {
+    writeln!(
+        w,
+        r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="{}" stroke="white" stroke-width="0.5"><title>{}</title></rect>"#,
+        x,
+        y,
+        width,
+        height,
+        fill,
+        escape(title)
+    )?;
+    Ok(())
+}
+
+/// Writes a label, truncated to fit in `width` pixels
+pub fn text(w: &mut impl Write, x: f64, y: f64, width: f64, size: f64, text: &str) -> Result {
+    if let Some(text) = fit(text, width, size) {
+        writeln!(
+            w,
+            r#"<text x="{:.2}" y="{:.2}" font-size="{}" pointer-events="none">{}</text>"#,
+            x,
+            y,
+            size,
+            escape(&text)
+        )?;
+    }
+    Ok(())
+}
+
+/// Rasterizes an SVG document into a PNG file
+#[cfg(feature = "png")]
+pub fn write_png(svg: &str, path: &Path) -> Result {
+    use resvg::usvg;
+
+    let options = resvg::Options::default();
+    let tree = usvg::Tree::from_str(svg, &options.usvg).map_err(|err| make_err(err.to_string()))?;
+    let image = resvg::backend_raqote::render_to_image(&tree, &options)
+        .ok_or_else(|| make_err("Failed to render the image"))?;
+    image
+        .write_png(path)
+        .map_err(|err| make_err(format!("{:?}", err)))?;
+    Ok(())
+}
+
+/// Truncates a text to fit in `width` pixels, assuming an average glyph width
+pub fn fit(text: &str, width: f64, size: f64) -> Option<String> {
+    let max = (width / (size * 0.6)) as usize;
+    let len = text.chars().count();
+    if len <= max {
+        Some(text.to_string())
+    } else if max >= 4 {
+        let mut ret: String = text.chars().take(max - 2).collect();
+        ret.push_str("..");
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+/// Escapes text for use in XML
+pub fn escape(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Returns the fill color of a category of files
+pub fn category_color(fte: &FileTypeExt) -> &'static str {
+    let ext = match fte {
+        FileTypeExt::File(ext) | FileTypeExt::Other(ext) => ext.to_lowercase(),
+        FileTypeExt::Dir => return "#b0b0b0",
+        FileTypeExt::Error => return "#d9534f",
+        _ => return "#909090",
+    };
+    match ext.as_str() {
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "webp" | "tiff" | "ico" => "#5cb85c",
+        "mp4" | "mkv" | "avi" | "mov" | "webm" | "flv" | "wmv" => "#8e44ad",
+        "mp3" | "flac" | "wav" | "ogg" | "aac" | "m4a" => "#c678dd",
+        "zip" | "tar" | "gz" | "xz" | "bz2" | "zst" | "7z" | "rar" | "iso" | "deb" | "rpm" => {
+            "#f0ad4e"
+        }
+        "log" | "out" | "err" => "#e67e22",
+        "txt" | "md" | "pdf" | "doc" | "docx" | "xls" | "xlsx" | "ppt" | "pptx" | "csv" => {
+            "#5bc0de"
+        }
+        "rs" | "c" | "h" | "cpp" | "py" | "js" | "ts" | "java" | "go" | "rb" | "php" | "sh" => {
+            "#337ab7"
+        }
+        "so" | "dll" | "a" | "o" | "exe" | "class" | "jar" | "rlib" | "pyc" => "#2c3e50",
+        "db" | "sqlite" | "sql" | "json" | "xml" | "yaml" | "yml" | "toml" => "#16a085",
+        _ => "#95a5a6",
+    }
+}
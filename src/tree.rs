@@ -0,0 +1,221 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::io::Write;
+#[cfg(feature = "history")]
+use std::path::Path;
+
+use terminal_size::terminal_size;
+
+use crate::cli;
+use crate::crawl::Node;
+#[cfg(feature = "history")]
+use crate::history;
+
+/// The width of the size column
+const SIZE_WIDTH: usize = 10;
+/// The width of the percentage column
+const PERCENT_WIDTH: usize = 4;
+/// The narrowest a name is truncated to, even if the line overflows the terminal
+const MIN_NAME_WIDTH: usize = 12;
+
+pub struct Options {
+    /// The number of directory levels to print below the root
+    pub depth: usize,
+    /// The number of largest entries to print in each directory
+    pub entries: usize,
+    /// The number of columns to fit lines in
+    pub width: usize,
+}
+
+/// The width of the terminal on standard output, or 80 if it is not a terminal
+pub fn screen_width() -> usize {
+    terminal_size().map_or(80, |size| (size.0).0 as usize)
+}
+
+/// Prints a tree sorted by size,
+/// with bars and percentages proportional to the size of the root
+pub fn write(node: &Node, title: &str, options: &Options, w: &mut impl Write) -> Result {
+    let bar_width = (options.width / 4).max(8).min(40);
+    let mut printer = Printer {
+        total: node.total().size().real,
+        bar_width,
+        options,
+        prefix: String::new(),
+        w,
+    };
+    printer.line(title, node.total().size().real, "")?;
+    printer.children(node, 1)
+}
+
+struct Printer<'a, W: Write> {
+    total: u64,
+    bar_width: usize,
+    options: &'a Options,
+    /// The tree drawing characters of the ancestors of the current line
+    prefix: String,
+    w: &'a mut W,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    fn children(&mut self, node: &Node, depth: usize) -> Result {
+        let (children, stats) = match node {
+            Node::Dir {
+                children, stats, ..
+            } => (children, stats),
+            _ => return Ok(()),
+        };
+
+        let mut sorted: Vec<&Node> = children.iter().collect();
+        sorted.sort_by_key(|child| Reverse(child.total().size().real));
+
+        let shown = sorted.len().min(self.options.entries);
+        let hidden = &sorted[shown..];
+        let hidden_size: u64 = hidden.iter().map(|child| child.total().size().real).sum();
+
+        // leaf shaking removes entries without removing their stats from the parent
+        let mut shaken_count = stats.total.files.count + stats.total.others.count;
+        let mut shaken_size = stats.total.files.size.real + stats.total.others.size.real;
+        for child in children {
+            let total = child.total();
+            shaken_count = shaken_count.saturating_sub(total.files.count + total.others.count);
+            shaken_size =
+                shaken_size.saturating_sub(total.files.size.real + total.others.size.real);
+        }
+
+        let mut rows: Vec<(Option<&Node>, String, u64)> = sorted[..shown]
+            .iter()
+            .map(|child| (Some(*child), label(child), child.total().size().real))
+            .collect();
+        if !hidden.is_empty() {
+            rows.push((
+                None,
+                format!("({} more entries)", hidden.len()),
+                hidden_size,
+            ));
+        }
+        if shaken_count > 0 {
+            rows.push((
+                None,
+                format!("({} shaken entries)", shaken_count),
+                shaken_size,
+            ));
+        }
+
+        let count = rows.len();
+        for (i, (child, label, size)) in rows.into_iter().enumerate() {
+            let last = i + 1 == count;
+            self.line(&label, size, if last { "└─ " } else { "├─ " })?;
+
+            if let Some(child) = child {
+                if depth < self.options.depth {
+                    let len = self.prefix.len();
+                    self.prefix.push_str(if last { "   " } else { "│  " });
+                    self.children(child, depth + 1)?;
+                    self.prefix.truncate(len);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, name: &str, size: u64, connector: &str) -> Result {
+        let ratio = if self.total == 0 {
+            0.0
+        } else {
+            size as f64 / self.total as f64
+        };
+        let filled = ((self.bar_width as f64) * ratio).round() as usize;
+        let bar = format!(
+            "{}{}",
+            "█".repeat(filled),
+            "░".repeat(self.bar_width - filled)
+        );
+
+        let used = SIZE_WIDTH
+            + 1
+            + self.bar_width
+            + 1
+            + PERCENT_WIDTH
+            + 1
+            + self.prefix.chars().count()
+            + connector.chars().count();
+        let name_width = self.options.width.saturating_sub(used).max(MIN_NAME_WIDTH);
+
+        writeln!(
+            self.w,
+            "{:>size_width$} {} {:>3.0}% {}{}{}",
+            cli::Byte(size).to_string(),
+            bar,
+            ratio * 100.0,
+            self.prefix,
+            connector,
+            truncate(name, name_width),
+            size_width = SIZE_WIDTH,
+        )?;
+        Ok(())
+    }
+}
+
+/// The name of a node, marking directories and errors
+pub fn label(node: &Node) -> String {
+    let name = node.name().map_or("", |name| &**name);
+    match node {
+        Node::Dir { .. } => format!("{}/", name),
+        Node::Error { error, .. } => format!("{} (error: {})", name, error),
+        _ => name.to_string(),
+    }
+}
+
+/// Shortens a string to `width` characters, marking the truncation with an ellipsis
+fn truncate(str: &str, width: usize) -> String {
+    if str.chars().count() <= width {
+        return str.to_string();
+    }
+    let mut truncated: String = str.chars().take(width - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Prints a history snapshot as a tree
+#[cfg(feature = "history")]
+pub async fn run(args: &cli::TreeArgs, history_dir: &Path, default_root: &Path) -> Result {
+    let path = history::resolve_snapshot(history_dir, &args.snapshot, default_root).await?;
+    let report = history::read(&path).await?;
+
+    let node = match &args.path {
+        Some(path) => report
+            .tree
+            .find(path)
+            .ok_or_else(|| make_err(format!("{}: no such path in the snapshot", path.display())))?,
+        None => &report.tree,
+    };
+    let title = match &args.path {
+        Some(path) => report.meta.root.join(path),
+        None => report.meta.root.clone(),
+    };
+
+    let stdout = std::io::stdout();
+    write(
+        node,
+        &title.to_string_lossy(),
+        &args.view.options(),
+        &mut stdout.lock(),
+    )
+}
@@ -0,0 +1,186 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::env;
+use std::path::Path;
+
+use chrono::offset::Local as LocalTz;
+use chrono::TimeZone;
+
+use crate::cli;
+use crate::diff::signed_size;
+use crate::history::{self, SeriesPoint};
+
+const DAY: f64 = 86400.0;
+
+/// A line fitted to the sizes over time, of which only the growth rate is used
+#[derive(Debug, Clone, Copy)]
+pub struct Fit {
+    /// The growth in bytes per second
+    pub slope: f64,
+}
+
+/// Fits a line to the points by ordinary least squares.
+///
+/// Returns `None` if there are less than two distinct x values.
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<Fit> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+
+    let mut sxx = 0.0;
+    let mut sxy = 0.0;
+    for &(x, y) in points {
+        sxx += (x - mean_x) * (x - mean_x);
+        sxy += (x - mean_x) * (y - mean_y);
+    }
+    if points.len() < 2 || sxx == 0.0 {
+        return None;
+    }
+
+    Some(Fit { slope: sxy / sxx })
+}
+
+/// Fits a line to the points by the Theil-Sen estimator,
+/// which is robust against outliers such as a one-off cleanup.
+///
+/// Returns `None` if there are less than two distinct x values.
+pub fn theil_sen_fit(points: &[(f64, f64)]) -> Option<Fit> {
+    let mut slopes = vec![];
+    for (i, &(x1, y1)) in points.iter().enumerate() {
+        for &(x2, y2) in &points[i + 1..] {
+            if x1 != x2 {
+                slopes.push((y2 - y1) / (x2 - x1));
+            }
+        }
+    }
+    let slope = median(&mut slopes)?;
+    Some(Fit { slope })
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).expect("NaN in regression"));
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
+
+/// Formats a growth rate in bytes per second as a size per day
+pub fn daily_rate(slope: f64) -> String {
+    format!("{}/day", signed_size((slope * DAY).round() as i64))
+}
+
+pub async fn run(args: &cli::TrendArgs, history_dir: &Path, default_root: &Path) -> Result {
+    let path = if args.path.is_absolute() {
+        args.path.clone()
+    } else {
+        env::current_dir()?.join(&args.path)
+    };
+    let path = path.canonicalize().unwrap_or(path);
+
+    let mut series = vec![];
+    for dir in history::select_namespaces(history_dir, &args.namespace, default_root).await? {
+        series.extend(history::path_series(&dir, &path, i64::min_value()).await?);
+    }
+    series.sort_by_key(|point| point.entry.timestamp);
+    if series.is_empty() {
+        return Err(make_err(format!(
+            "{}: not covered by any snapshot",
+            path.display()
+        )));
+    }
+
+    println!("{}", path.display());
+    let mut last = None;
+    for point in &series {
+        let date = LocalTz
+            .timestamp(point.entry.timestamp, 0)
+            .format("%Y-%m-%d %H:%M");
+        match &point.stats {
+            Some(stats) => {
+                let delta = match last {
+                    Some(last) => signed_size(stats.size.real as i64 - last as i64),
+                    None => String::new(),
+                };
+                println!(
+                    "{}  {:>12}  {:>12}",
+                    date,
+                    cli::Byte(stats.size.real),
+                    delta
+                );
+                last = Some(stats.size.real);
+            }
+            None => println!("{}  {:>12}", date, "-"),
+        }
+    }
+
+    let total_points = points(&series, |stats| Some(stats.size.real));
+    match linear_fit(&total_points) {
+        Some(fit) => println!("Growth: {}", daily_rate(fit.slope)),
+        None => println!("Growth: not enough snapshots"),
+    }
+
+    let latest = match series.iter().rev().find_map(|point| point.stats.as_ref()) {
+        Some(stats) => stats,
+        None => return Ok(()),
+    };
+    let mut children: Vec<_> = latest.children.iter().collect();
+    children.sort_by_key(|(_, size)| Reverse(size.real));
+    if children.is_empty() {
+        return Ok(());
+    }
+
+    println!();
+    println!("Top children:");
+    for (name, size) in children.into_iter().take(args.children) {
+        let child_points = points(&series, |stats| {
+            stats
+                .children
+                .iter()
+                .find(|(child, _)| child == name)
+                .map(|(_, size)| size.real)
+        });
+        let rate = match linear_fit(&child_points) {
+            Some(fit) => daily_rate(fit.slope),
+            None => "-".to_string(),
+        };
+        println!("{:>12}  {:>16}  {}", cli::Byte(size.real), rate, name);
+    }
+
+    Ok(())
+}
+
+fn points<F>(series: &[SeriesPoint], f: F) -> Vec<(f64, f64)>
+where
+    F: Fn(&history::PathStats) -> Option<u64>,
+{
+    series
+        .iter()
+        .filter_map(|point| {
+            let y = f(point.stats.as_ref()?)?;
+            Some((point.entry.timestamp as f64, y as f64))
+        })
+        .collect()
+}
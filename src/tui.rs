@@ -0,0 +1,443 @@
+// fstree
+// Copyright (C) SOFe
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affer General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+#[allow(unused_imports)]
+use crate::result::{make_err, Result};
+
+use std::cmp::Reverse;
+use std::io::{self, Stdout, Write};
+#[cfg(feature = "history")]
+use std::path::Path;
+use std::path::PathBuf;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::crawl::{FileTypeExt, Node, StringRef, TypedStats};
+#[cfg(feature = "history")]
+use crate::history;
+use crate::{cli, tree};
+
+/// The quantity that entries are sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Real,
+    Content,
+    Count,
+}
+
+impl Sort {
+    fn next(self) -> Self {
+        match self {
+            Sort::Real => Sort::Content,
+            Sort::Content => Sort::Count,
+            Sort::Count => Sort::Real,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Sort::Real => "real size",
+            Sort::Content => "content size",
+            Sort::Count => "count",
+        }
+    }
+
+    fn of(self, stats: &TypedStats) -> u64 {
+        match self {
+            Sort::Real => stats.size().real,
+            Sort::Content => stats.size().content,
+            Sort::Count => (stats.files.count + stats.others.count) as u64,
+        }
+    }
+
+    fn display(self, value: u64) -> String {
+        match self {
+            Sort::Real | Sort::Content => cli::Byte(value).to_string(),
+            Sort::Count => value.to_string(),
+        }
+    }
+}
+
+/// What the list on the screen shows
+enum Mode {
+    /// The entries of the current directory
+    Entries,
+    /// The breakdown by extension of the current directory
+    Extensions,
+    /// The snapshots in the history of the scanned directory
+    #[cfg(feature = "history")]
+    Snapshots(Vec<history::IndexEntry>),
+}
+
+/// An interactive browser of a tree in the terminal
+pub struct Browser {
+    root: PathBuf,
+    tree: Node,
+    /// The names of the directories from the root to the current directory
+    path: Vec<StringRef>,
+    /// The selected row in each directory of `path`, restored when leaving a directory
+    selections: Vec<usize>,
+    selected: usize,
+    /// The first visible row
+    offset: usize,
+    sort: Sort,
+    mode: Mode,
+    /// A one-off message shown in the status line
+    message: Option<String>,
+    /// The history namespace to open snapshots from
+    #[cfg(feature = "history")]
+    pub namespace_dir: Option<PathBuf>,
+}
+
+impl Browser {
+    pub fn new(root: PathBuf, tree: Node) -> Self {
+        Self {
+            root,
+            tree,
+            path: vec![],
+            selections: vec![],
+            selected: 0,
+            offset: 0,
+            sort: Sort::Real,
+            mode: Mode::Entries,
+            message: None,
+            #[cfg(feature = "history")]
+            namespace_dir: None,
+        }
+    }
+
+    /// Runs the browser until the user quits
+    pub async fn run(mut self) -> Result {
+        let _screen = Screen::enter()?;
+        let mut stdout = io::stdout();
+        loop {
+            self.draw(&mut stdout)?;
+            let key = match event::read().map_err(make_err)? {
+                Event::Key(key) => key.code,
+                _ => continue,
+            };
+            self.message = None;
+            let rows = self.rows().len();
+            let page = page_height()?;
+            match key {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.selected = (self.selected + 1).min(rows.saturating_sub(1))
+                }
+                KeyCode::PageUp => self.selected = self.selected.saturating_sub(page),
+                KeyCode::PageDown => {
+                    self.selected = (self.selected + page).min(rows.saturating_sub(1))
+                }
+                KeyCode::Home => self.selected = 0,
+                KeyCode::End => self.selected = rows.saturating_sub(1),
+                KeyCode::Char('s') => self.sort = self.sort.next(),
+                KeyCode::Char('e') => {
+                    self.mode = match self.mode {
+                        Mode::Extensions => Mode::Entries,
+                        _ => Mode::Extensions,
+                    };
+                    self.selected = 0;
+                }
+                #[cfg(feature = "history")]
+                KeyCode::Char('H') => self.open_history().await,
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => self.enter().await,
+                KeyCode::Esc | KeyCode::Left | KeyCode::Backspace | KeyCode::Char('h') => {
+                    self.leave()
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn current(&self) -> &Node {
+        let mut node = &self.tree;
+        for name in &self.path {
+            node = match node {
+                Node::Dir { children, .. } => children
+                    .iter()
+                    .find(|child| child.name() == Some(name))
+                    .expect("the current path was taken from the tree"),
+                _ => unreachable!("only directories are entered"),
+            };
+        }
+        node
+    }
+
+    /// The entries of the current directory in sort order
+    fn entries(&self) -> Vec<&Node> {
+        let mut entries: Vec<&Node> = match self.current() {
+            Node::Dir { children, .. } => children.iter().collect(),
+            _ => vec![],
+        };
+        let sort = self.sort;
+        entries.sort_by_key(|child| Reverse(sort.of(&child.total())));
+        entries
+    }
+
+    /// The rows of the list as (value for the bar, displayed value, label)
+    fn rows(&self) -> Vec<(u64, String, String)> {
+        let sort = self.sort;
+        match &self.mode {
+            Mode::Entries => self
+                .entries()
+                .into_iter()
+                .map(|child| {
+                    let value = sort.of(&child.total());
+                    (value, sort.display(value), tree::label(child))
+                })
+                .collect(),
+            Mode::Extensions => {
+                let mut extensions: Vec<(FileTypeExt, TypedStats)> =
+                    self.current().stats().by_extension;
+                extensions.sort_by_key(|(_, typed)| Reverse(sort.of(typed)));
+                extensions
+                    .into_iter()
+                    .map(|(fte, typed)| {
+                        let value = sort.of(&typed);
+                        let count = typed.files.count + typed.dirs.count + typed.others.count;
+                        let label = format!("{}  ({} entries)", fte, count);
+                        (value, sort.display(value), label)
+                    })
+                    .collect()
+            }
+            #[cfg(feature = "history")]
+            Mode::Snapshots(entries) => entries
+                .iter()
+                .rev()
+                .map(|entry| {
+                    let label = format!(
+                        "{}  {} files  {} errors",
+                        entry.file, entry.files, entry.errors
+                    );
+                    let value = entry.size.real;
+                    (value, cli::Byte(value).to_string(), label)
+                })
+                .collect(),
+        }
+    }
+
+    async fn enter(&mut self) {
+        match &self.mode {
+            Mode::Entries => {
+                let name = match self.entries().get(self.selected) {
+                    Some(Node::Dir { name, .. }) => name.clone(),
+                    _ => return,
+                };
+                self.path.push(name);
+                self.selections.push(self.selected);
+                self.selected = 0;
+                self.offset = 0;
+            }
+            Mode::Extensions => {}
+            #[cfg(feature = "history")]
+            Mode::Snapshots(entries) => {
+                let entry = match entries.iter().rev().nth(self.selected) {
+                    Some(entry) => entry.file.clone(),
+                    None => return,
+                };
+                let dir = match &self.namespace_dir {
+                    Some(dir) => dir.clone(),
+                    None => return,
+                };
+                self.open_snapshot(&dir.join(entry)).await;
+            }
+        }
+    }
+
+    fn leave(&mut self) {
+        match self.mode {
+            Mode::Entries => {
+                if self.path.pop().is_some() {
+                    self.selected = self.selections.pop().unwrap_or(0);
+                }
+            }
+            _ => {
+                self.mode = Mode::Entries;
+                self.selected = 0;
+            }
+        }
+    }
+
+    #[cfg(feature = "history")]
+    async fn open_history(&mut self) {
+        let dir = match &self.namespace_dir {
+            Some(dir) => dir,
+            None => {
+                self.message = Some("No history to open snapshots from".to_string());
+                return;
+            }
+        };
+        match history::list(dir).await {
+            Ok(entries) => {
+                self.mode = Mode::Snapshots(entries);
+                self.selected = 0;
+            }
+            Err(err) => self.message = Some(format!("Failed to list history: {}", err)),
+        }
+    }
+
+    #[cfg(feature = "history")]
+    async fn open_snapshot(&mut self, path: &Path) {
+        match history::read(path).await {
+            Ok(report) => {
+                self.root = report.meta.root;
+                self.tree = report.tree;
+                self.path.clear();
+                self.selections.clear();
+                self.mode = Mode::Entries;
+                self.selected = 0;
+                self.message = Some(format!("Opened {}", path.display()));
+            }
+            Err(err) => self.message = Some(format!("Failed to read {}: {}", path.display(), err)),
+        }
+    }
+
+    fn draw(&mut self, w: &mut Stdout) -> Result {
+        let (width, _) = terminal::size().map_err(make_err)?;
+        let width = width as usize;
+        let page = page_height()?;
+        let rows = self.rows();
+
+        self.selected = self.selected.min(rows.len().saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + page {
+            self.offset = self.selected + 1 - page;
+        }
+
+        let mut title = self.root.clone();
+        for name in &self.path {
+            title.push(&**name);
+        }
+        let total = self.sort.of(&self.current().total());
+        let header = match self.mode {
+            Mode::Entries => format!(
+                "{}  {}  (sorted by {})",
+                title.display(),
+                self.sort.display(total),
+                self.sort.name()
+            ),
+            Mode::Extensions => format!(
+                "{}  by extension  (sorted by {})",
+                title.display(),
+                self.sort.name()
+            ),
+            #[cfg(feature = "history")]
+            Mode::Snapshots(_) => "Snapshots in history".to_string(),
+        };
+
+        queue!(w, Clear(ClearType::All), MoveTo(0, 0)).map_err(make_err)?;
+        queue!(
+            w,
+            SetAttribute(Attribute::Bold),
+            Print(fit(&header, width)),
+            SetAttribute(Attribute::Reset)
+        )
+        .map_err(make_err)?;
+
+        let bar_width = (width / 5).max(8).min(30);
+        let max = rows.iter().map(|&(value, _, _)| value).max().unwrap_or(0);
+        for (i, (value, display, label)) in rows.iter().enumerate().skip(self.offset).take(page) {
+            let filled = if max == 0 {
+                0
+            } else {
+                ((bar_width as u64 * value + max / 2) / max) as usize
+            };
+            let line = format!(
+                "{:>10} [{}{}] {}",
+                display,
+                "#".repeat(filled),
+                " ".repeat(bar_width - filled),
+                label
+            );
+            queue!(w, MoveTo(0, (i - self.offset + 1) as u16)).map_err(make_err)?;
+            if i == self.selected {
+                queue!(
+                    w,
+                    SetAttribute(Attribute::Reverse),
+                    Print(fit(&line, width)),
+                    SetAttribute(Attribute::Reset)
+                )
+                .map_err(make_err)?;
+            } else {
+                queue!(w, Print(fit(&line, width))).map_err(make_err)?;
+            }
+        }
+
+        let help = if cfg!(feature = "history") {
+            "enter: open  backspace: back  s: sort  e: extensions  H: history  q: quit"
+        } else {
+            "enter: open  backspace: back  s: sort  e: extensions  q: quit"
+        };
+        let status = self.message.as_ref().map_or(help, String::as_str);
+        queue!(
+            w,
+            MoveTo(0, page as u16 + 1),
+            SetAttribute(Attribute::Reverse),
+            Print(fit(status, width)),
+            SetAttribute(Attribute::Reset)
+        )
+        .map_err(make_err)?;
+        w.flush()?;
+        Ok(())
+    }
+}
+
+/// Switches the terminal to the alternate screen in raw mode until dropped
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode().map_err(make_err)?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide).map_err(make_err)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The number of list rows that fit between the header and the status line
+fn page_height() -> Result<usize> {
+    let (_, height) = terminal::size().map_err(make_err)?;
+    Ok((height as usize).saturating_sub(2).max(1))
+}
+
+/// Truncates or pads a line to exactly `width` characters
+fn fit(line: &str, width: usize) -> String {
+    let mut fitted: String = line.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat(' ').take(width - len));
+    fitted
+}
+
+/// Browses a history snapshot
+#[cfg(feature = "history")]
+pub async fn run(args: &cli::BrowseArgs, history_dir: &Path, default_root: &Path) -> Result {
+    let path = history::resolve_snapshot(history_dir, &args.snapshot, default_root).await?;
+    let report = history::read(&path).await?;
+    let mut browser = Browser::new(report.meta.root, report.tree);
+    browser.namespace_dir = path.parent().map(Path::to_path_buf);
+    browser.run().await
+}
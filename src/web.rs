@@ -27,16 +27,29 @@ use actix_web::web::{Data, Json};
 use actix_web::{App, HttpResponse, HttpServer, Responder};
 use include_flate::flate;
 use lazy_static::lazy_static;
+#[cfg(feature = "history")]
+use serde::Serialize;
 use tempdir::TempDir;
 
 use crate::crawl;
+#[cfg(feature = "history")]
+use crate::forecast;
+
+/// The data served by the web frontend
+pub struct Context {
+    /// The report of the scan in this run, if any
+    pub current: Option<crawl::Node>,
+    /// Forecasts of when filesystems fill up, computed from history
+    #[cfg(feature = "history")]
+    pub forecasts: Vec<forecast::Forecast>,
+}
 
-pub fn run(current: Option<crawl::Node>, ip: &str, port: u16) -> Result {
+pub fn run(context: Context, ip: &str, port: u16) -> Result {
     log::debug!("Extracting assets");
     let temp_dir = extract_assets()?;
 
     log::info!("Starting web server on {}:{}", ip, port);
-    serve(ip, port, current, temp_dir)?;
+    serve(ip, port, context, temp_dir)?;
 
     Ok(())
 }
@@ -55,6 +68,8 @@ fn extract_assets() -> Result<TempDir> {
 }
 
 type Reports = Arc<RwLock<HashMap<String, crawl::Node>>>;
+#[cfg(feature = "history")]
+type Forecasts = Arc<Vec<forecast::Forecast>>;
 lazy_static! {
     static ref LATEST_REPORT_NAME: String = "Latest Report".to_string();
 }
@@ -89,22 +104,51 @@ fn load_report(name: String, reports: Data<Reports>) -> Json<bool> {
     unimplemented!()
 }
 
-fn serve(ip: &str, port: u16, current: Option<crawl::Node>, temp_dir: TempDir) -> Result {
+#[cfg(feature = "history")]
+#[derive(Serialize)]
+struct ForecastView {
+    message: String,
+    #[serde(flatten)]
+    forecast: forecast::Forecast,
+}
+
+#[cfg(feature = "history")]
+#[actix_web::post("/xhr/forecasts")]
+fn forecasts(forecasts: Data<Forecasts>) -> Json<Vec<ForecastView>> {
+    Json(
+        forecasts
+            .iter()
+            .map(|forecast| ForecastView {
+                message: forecast.to_string(),
+                forecast: forecast.clone(),
+            })
+            .collect(),
+    )
+}
+
+fn serve(ip: &str, port: u16, context: Context, temp_dir: TempDir) -> Result {
     let mut map = HashMap::new();
-    if let Some(current) = current {
+    if let Some(current) = context.current {
         map.insert(LATEST_REPORT_NAME.clone(), current);
     }
     let reports: Reports = Arc::new(RwLock::new(map));
+    #[cfg(feature = "history")]
+    let forecasts: Forecasts = Arc::new(context.forecasts);
     let temp_dir = Arc::new(temp_dir);
 
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .data(Reports::clone(&reports))
             .service(actix_files::Files::new("/pkg", temp_dir.path()))
             .service(index)
             .service(has_current)
             .service(list_reports)
-            .service(load_report)
+            .service(load_report);
+        #[cfg(feature = "history")]
+        let app = app
+            .data(Forecasts::clone(&forecasts))
+            .service(self::forecasts);
+        app
     })
     .bind((ip, port))?;
 